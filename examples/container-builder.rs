@@ -0,0 +1,34 @@
+use hassle_rs::*;
+
+fn main() {
+    let source = include_str!("copy.hlsl");
+
+    let dxc = Dxc::new(None).unwrap();
+    let compiler = dxc.create_compiler().unwrap();
+    let library = dxc.create_library().unwrap();
+
+    let blob = library.create_blob_with_encoding_from_str(source).unwrap();
+    let dxil = match compiler.compile(&blob, "copy.hlsl", "copyCs", "cs_6_0", &["-Zi"], None, &[]) {
+        Ok(result) => result.get_result().unwrap(),
+        Err(result) => {
+            let error_blob = result.0.get_error_buffer().unwrap();
+            panic!(
+                "{}",
+                library.get_blob_as_string(&error_blob.into()).unwrap()
+            );
+        }
+    };
+
+    let builder = dxc.create_container_builder().unwrap();
+    builder.load(&dxil).unwrap();
+    builder.remove_part(DFCC_SHADER_DEBUG_INFO_DXIL).unwrap();
+
+    let mut stripped = builder.serialize_container().unwrap();
+    assert!(fake_sign_dxil_in_place(stripped.as_mut()));
+
+    println!(
+        "Stripped debug info: {} -> {} bytes",
+        dxil.as_slice::<u8>().len(),
+        stripped.as_slice::<u8>().len()
+    );
+}
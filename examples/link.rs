@@ -0,0 +1,54 @@
+use hassle_rs::*;
+
+fn main() {
+    let source = "
+        RWStructuredBuffer<float> g_output : register(u0);
+
+        float square(float x);
+
+        [shader(\"compute\")]
+        [numthreads(64, 1, 1)]
+        void copyCs(uint3 dispatchThreadId : SV_DispatchThreadID)
+        {
+            g_output[dispatchThreadId.x] = square(dispatchThreadId.x);
+        }";
+
+    let helpers = "
+        export float square(float x)
+        {
+            return x * x;
+        }";
+
+    let dxc = Dxc::new(None).unwrap();
+    let compiler = dxc.create_compiler().unwrap();
+    let library = dxc.create_library().unwrap();
+    let linker = dxc.create_linker().unwrap();
+
+    for (name, source) in [("main.hlsl", source), ("helpers.hlsl", helpers)] {
+        let blob = library.create_blob_with_encoding_from_str(source).unwrap();
+        let lib = match compiler.compile(&blob, name, "", "lib_6_3", &[], None, &[]) {
+            Ok(result) => result.get_result().unwrap(),
+            Err(result) => {
+                let error_blob = result.0.get_error_buffer().unwrap();
+                panic!(
+                    "{}",
+                    library.get_blob_as_string(&error_blob.into()).unwrap()
+                );
+            }
+        };
+        linker.register_library(name, &lib).unwrap();
+    }
+
+    match linker.link("copyCs", "cs_6_3", &["main.hlsl", "helpers.hlsl"], &[]) {
+        Ok(result) => {
+            let dxil = result.get_result().unwrap();
+            println!("Linked {} bytes of DXIL", dxil.as_slice::<u8>().len());
+        }
+        Err((result, error)) => {
+            for diagnostic in result.get_diagnostics().unwrap() {
+                println!("{}", diagnostic);
+            }
+            panic!("Failed to link: {}", error);
+        }
+    }
+}
@@ -22,7 +22,8 @@ fn main() {
 
     for (idx, source) in sources.iter().enumerate() {
         println!("Testing file: {}", idx);
-        let mut dxil = compile_hlsl("copy.hlsl", source, "copyCs", "cs_6_0", &[], &[]).unwrap();
+        let mut dxil =
+            compile_hlsl("copy.hlsl", source, "copyCs", "cs_6_0", &[], None, &[]).unwrap();
 
         let without_digest = get_digest(&dxil);
 
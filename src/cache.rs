@@ -0,0 +1,721 @@
+use crate::diagnostics::{parse_diagnostics, Diagnostic};
+use crate::utils::{panic_message, DefaultIncludeHandler, HassleError, Result};
+use crate::wrapper::{
+    Dxc, DxcCompiler, DxcIdentity, DxcIncludeError, DxcIncludeHandler2, DxcIncludeSource,
+    DxcLibrary,
+};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::convert::TryInto;
+use std::fs;
+use std::io;
+use std::panic::AssertUnwindSafe;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+const OBJECT_MAGIC: &[u8] = b"HASSLE-CACHE-1\n";
+
+/// Number of include configurations remembered per set of compile inputs.
+const MAX_MANIFEST_ENTRIES: usize = 16;
+
+/// SHA-256 over length-prefixed fields, so that consecutive fields cannot run into each other.
+struct CacheHasher {
+    hasher: Sha256,
+}
+
+impl CacheHasher {
+    fn new() -> Self {
+        Self {
+            hasher: Sha256::new(),
+        }
+    }
+
+    fn field(&mut self, data: impl AsRef<[u8]>) {
+        let data = data.as_ref();
+        self.hasher.update((data.len() as u64).to_le_bytes());
+        self.hasher.update(data);
+    }
+
+    fn finish(self) -> String {
+        self.hasher
+            .finalize()
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect()
+    }
+}
+
+fn hash_include_source(source: &DxcIncludeSource) -> String {
+    let mut hasher = CacheHasher::new();
+    hasher.field(format!("{:?}", source.encoding));
+    hasher.field(&source.data);
+    hasher.finish()
+}
+
+/// A single call to the include handler and its outcome, `hash` is `None` when the
+/// file was not found.
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct IncludeLookup {
+    filename: String,
+    includer: Option<String>,
+    hash: Option<String>,
+}
+
+impl IncludeLookup {
+    fn to_line(&self) -> String {
+        format!(
+            "lookup\t{}\t{}\t{}",
+            self.hash.as_deref().unwrap_or("-"),
+            self.includer.as_deref().unwrap_or(""),
+            self.filename
+        )
+    }
+
+    fn from_line(line: &str) -> Option<Self> {
+        let mut parts = line.splitn(4, '\t');
+        if parts.next()? != "lookup" {
+            return None;
+        }
+        let hash = parts.next()?;
+        let includer = parts.next()?;
+        let filename = parts.next()?;
+
+        Some(Self {
+            filename: filename.to_owned(),
+            includer: Some(includer).filter(|i| !i.is_empty()).map(str::to_owned),
+            hash: Some(hash).filter(|h| *h != "-").map(str::to_owned),
+        })
+    }
+}
+
+/// Include configuration that was seen for a set of compile inputs, and the key of
+/// the output it produced.
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct ManifestEntry {
+    object_key: String,
+    lookups: Vec<IncludeLookup>,
+}
+
+fn parse_manifest(text: &str) -> Vec<ManifestEntry> {
+    let mut entries: Vec<ManifestEntry> = vec![];
+    for line in text.lines() {
+        if let Some(object_key) = line.strip_prefix("entry\t") {
+            entries.push(ManifestEntry {
+                object_key: object_key.to_owned(),
+                lookups: vec![],
+            });
+        } else if let (Some(lookup), Some(entry)) =
+            (IncludeLookup::from_line(line), entries.last_mut())
+        {
+            entry.lookups.push(lookup);
+        }
+    }
+    entries
+}
+
+fn write_manifest(entries: &[ManifestEntry]) -> String {
+    let mut text = String::new();
+    for entry in entries {
+        text.push_str("entry\t");
+        text.push_str(&entry.object_key);
+        text.push('\n');
+        for lookup in &entry.lookups {
+            text.push_str(&lookup.to_line());
+            text.push('\n');
+        }
+    }
+    text
+}
+
+/// Forwards includes to the user handler and records every lookup.
+struct RecordingIncludeHandler<'h> {
+    inner: &'h mut dyn DxcIncludeHandler2,
+    lookups: Vec<IncludeLookup>,
+    /// A handler failure depends on more than the file contents, so such results are not cached.
+    failed: bool,
+}
+
+impl<'h> DxcIncludeHandler2 for RecordingIncludeHandler<'h> {
+    fn load_include(
+        &mut self,
+        filename: &str,
+        includer: Option<&str>,
+    ) -> Result<DxcIncludeSource, DxcIncludeError> {
+        let result = self.inner.load_include(filename, includer);
+
+        let hash = match &result {
+            Ok(source) => Some(hash_include_source(source)),
+            Err(DxcIncludeError::NotFound) => None,
+            Err(DxcIncludeError::Failed(_)) => {
+                self.failed = true;
+                return result;
+            }
+        };
+        self.lookups.push(IncludeLookup {
+            filename: filename.to_owned(),
+            includer: includer.map(str::to_owned),
+            hash,
+        });
+
+        result
+    }
+}
+
+/// Writes `data` to a temporary file next to `path` and renames it into place, so that
+/// other processes never observe a partially written file.
+fn write_atomic(path: &Path, data: &[u8]) -> io::Result<()> {
+    static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+    let tmp_path = path.with_extension(format!(
+        "tmp-{}-{}",
+        std::process::id(),
+        COUNTER.fetch_add(1, Ordering::Relaxed)
+    ));
+
+    fs::write(&tmp_path, data)?;
+    let result = fs::rename(&tmp_path, path);
+    if result.is_err() {
+        let _ = fs::remove_file(&tmp_path);
+    }
+    result
+}
+
+/// Opt-in on-disk cache for compilation results, which can be shared by multiple
+/// processes.
+///
+/// Results are keyed by a hash of the source, entry point, target profile, arguments,
+/// defines, the version of the loaded compiler and the contents of every file the
+/// include handler was asked for. Compilers that do not report the commit they were
+/// built from are also told apart by the path they were loaded from. Like `ccache`, the includes of a previous compilation
+/// are loaded again through the include handler to check whether the cached result is
+/// still valid, so changing any header causes a recompile.
+///
+/// Files are written to a temporary file and renamed into place, so concurrent
+/// processes never read partial entries. Two processes storing at the same time may
+/// drop one of the two results, which only causes a recompile later.
+///
+/// ```no_run
+/// use hassle_rs::{CompileCache, Dxc};
+///
+/// let dxc = Dxc::new(None).unwrap();
+/// let cache = CompileCache::new(&dxc, "target/shader-cache")
+///     .unwrap()
+///     .max_size(256 * 1024 * 1024);
+///
+/// let source = std::fs::read_to_string("copy.hlsl").unwrap();
+/// let (dxil, warnings) = cache
+///     .compile("copy.hlsl", &source, "copyCs", "cs_6_0", &[], None, &[])
+///     .unwrap();
+/// ```
+#[derive(Debug)]
+pub struct CompileCache<'d> {
+    dxc: &'d Dxc,
+    compiler: DxcCompiler,
+    library: DxcLibrary,
+    /// Version of the compiler, and its path when the version has no commit.
+    version: String,
+    storage: CacheStorage,
+}
+
+impl<'d> CompileCache<'d> {
+    /// Opens or creates a cache in `dir`.
+    ///
+    /// Fails when the loaded compiler does not report its version, which is required
+    /// to keep results of different compilers apart.
+    pub fn new(dxc: &'d Dxc, dir: impl Into<PathBuf>) -> Result<Self> {
+        let dir = dir.into();
+        fs::create_dir_all(dir.join("manifests"))?;
+        fs::create_dir_all(dir.join("objects"))?;
+
+        // Different builds of the same release report the same `major.minor`
+        let version = dxc.version()?;
+        let version = match version.commit {
+            Some(_) => version.to_string(),
+            None => format!("{} {}", version, dxc.identity().path.display()),
+        };
+
+        Ok(Self {
+            dxc,
+            compiler: dxc.create_compiler()?,
+            library: dxc.create_library()?,
+            version,
+            storage: CacheStorage {
+                dir,
+                max_size: None,
+            },
+        })
+    }
+
+    /// Evicts the least recently used entries after storing a result when the cache
+    /// grows larger than `bytes`.
+    pub fn max_size(mut self, bytes: u64) -> Self {
+        self.storage.max_size = Some(bytes);
+        self
+    }
+
+    pub fn dxc(&self) -> &'d Dxc {
+        self.dxc
+    }
+
+    /// The library that produces the results in this cache.
+    pub fn identity(&self) -> &DxcIdentity {
+        self.dxc.identity()
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.storage.dir
+    }
+
+    /// Same as [`crate::compile_hlsl_with_diagnostics()`], but returns the cached output
+    /// and diagnostics when the inputs have been compiled before.
+    ///
+    /// Failed compilations are not cached.
+    pub fn compile(
+        &self,
+        source_name: &str,
+        shader_text: &str,
+        entry_point: &str,
+        target_profile: impl Into<String>,
+        args: &[&str],
+        include_handler: Option<&mut dyn DxcIncludeHandler2>,
+        defines: &[(&str, Option<&str>)],
+    ) -> Result<(Vec<u8>, Vec<Diagnostic>)> {
+        let target_profile = target_profile.into();
+
+        let mut default_include_handler = DefaultIncludeHandler {};
+        let include_handler = include_handler.unwrap_or(&mut default_include_handler);
+
+        let mut hasher = CacheHasher::new();
+        hasher.field(&self.version);
+        hasher.field(source_name);
+        hasher.field(shader_text);
+        hasher.field(entry_point);
+        hasher.field(&target_profile);
+        hasher.field(args.len().to_le_bytes());
+        for arg in args {
+            hasher.field(arg);
+        }
+        hasher.field(defines.len().to_le_bytes());
+        for (name, value) in defines {
+            hasher.field(name);
+            hasher.field(value.unwrap_or("1"));
+        }
+        let manifest_key = hasher.finish();
+
+        let manifest_path = self.storage.dir.join("manifests").join(&manifest_key);
+        let manifest = fs::read_to_string(&manifest_path)
+            .map(|text| parse_manifest(&text))
+            .unwrap_or_default();
+
+        if let Some(hit) = self
+            .storage
+            .lookup(&manifest_path, &manifest, include_handler)?
+        {
+            return Ok(hit);
+        }
+
+        let mut recording_handler = RecordingIncludeHandler {
+            inner: include_handler,
+            lookups: vec![],
+            failed: false,
+        };
+
+        let blob = self
+            .library
+            .create_blob_with_encoding_from_str(shader_text)?;
+        let result = self.compiler.compile(
+            &blob,
+            source_name,
+            entry_point,
+            target_profile,
+            args,
+            Some(&mut recording_handler),
+            defines,
+        );
+
+        let result = match result {
+            Ok(result) => result,
+            Err((_, error @ HassleError::IncludeHandlerPanic(_))) => return Err(error),
+            Err((result, _)) => {
+                let error_blob = result.get_error_buffer()?;
+                return Err(HassleError::CompileError(
+                    self.library.get_blob_as_string(&error_blob.into())?,
+                ));
+            }
+        };
+
+        let data = result.get_result()?.to_vec();
+        let diagnostics_text = result.get_error_buffer()?.to_string_lossy();
+
+        if !recording_handler.failed {
+            // A failure to store the result does not affect the compilation
+            let _ = self.storage.store(
+                &manifest_path,
+                &manifest_key,
+                manifest,
+                recording_handler.lookups,
+                &data,
+                &diagnostics_text,
+            );
+        }
+
+        let diagnostics = parse_diagnostics(&diagnostics_text);
+        Ok((data, diagnostics))
+    }
+
+    /// Removes all entries from the cache.
+    pub fn clear(&self) -> Result<()> {
+        self.storage.clear()
+    }
+}
+
+/// The files of a [`CompileCache`], independent of the compiler.
+#[derive(Debug)]
+struct CacheStorage {
+    dir: PathBuf,
+    max_size: Option<u64>,
+}
+
+impl CacheStorage {
+    fn clear(&self) -> Result<()> {
+        for subdir in ["manifests", "objects"].iter() {
+            for entry in fs::read_dir(self.dir.join(subdir))? {
+                let _ = fs::remove_file(entry?.path());
+            }
+        }
+        Ok(())
+    }
+
+    /// Replays the include lookups of every manifest entry through `include_handler`,
+    /// and returns the object of the first entry where they all have the same outcome.
+    fn lookup(
+        &self,
+        manifest_path: &Path,
+        manifest: &[ManifestEntry],
+        include_handler: &mut dyn DxcIncludeHandler2,
+    ) -> Result<Option<(Vec<u8>, Vec<Diagnostic>)>> {
+        let mut replayed: HashMap<(String, Option<String>), Option<Option<String>>> =
+            HashMap::new();
+
+        for entry in manifest.iter().rev() {
+            let mut matches = true;
+            for lookup in &entry.lookups {
+                let key = (lookup.filename.clone(), lookup.includer.clone());
+                let outcome = match replayed.get(&key) {
+                    Some(outcome) => outcome,
+                    None => {
+                        // Caught here like in the compiler, so that a panicking handler
+                        // is reported the same way on a hit and on a miss
+                        let result = std::panic::catch_unwind(AssertUnwindSafe(|| {
+                            include_handler
+                                .load_include(&lookup.filename, lookup.includer.as_deref())
+                        }))
+                        .map_err(|panic| {
+                            HassleError::IncludeHandlerPanic(panic_message(&*panic))
+                        })?;
+                        let outcome = match result {
+                            Ok(source) => Some(Some(hash_include_source(&source))),
+                            Err(DxcIncludeError::NotFound) => Some(None),
+                            // Never matches a recorded lookup
+                            Err(DxcIncludeError::Failed(_)) => None,
+                        };
+                        replayed.entry(key).or_insert(outcome)
+                    }
+                };
+
+                if outcome.as_ref() != Some(&lookup.hash) {
+                    matches = false;
+                    break;
+                }
+            }
+
+            if matches {
+                if let Some(hit) = self.read_object(&entry.object_key) {
+                    touch(manifest_path);
+                    return Ok(Some(hit));
+                }
+            }
+        }
+
+        Ok(None)
+    }
+
+    fn read_object(&self, object_key: &str) -> Option<(Vec<u8>, Vec<Diagnostic>)> {
+        let path = self.dir.join("objects").join(object_key);
+        let object = fs::read(&path).ok()?;
+
+        let rest = object.strip_prefix(OBJECT_MAGIC)?;
+        if rest.len() < 8 {
+            return None;
+        }
+        let (len, rest) = rest.split_at(8);
+        let len = u64::from_le_bytes(len.try_into().unwrap()) as usize;
+        if rest.len() < len {
+            return None;
+        }
+        let (data, diagnostics_text) = rest.split_at(len);
+
+        touch(&path);
+
+        Some((
+            data.to_vec(),
+            parse_diagnostics(&String::from_utf8_lossy(diagnostics_text)),
+        ))
+    }
+
+    fn store(
+        &self,
+        manifest_path: &Path,
+        manifest_key: &str,
+        mut manifest: Vec<ManifestEntry>,
+        lookups: Vec<IncludeLookup>,
+        data: &[u8],
+        diagnostics_text: &str,
+    ) -> io::Result<()> {
+        let mut hasher = CacheHasher::new();
+        hasher.field(manifest_key);
+        for lookup in &lookups {
+            hasher.field(lookup.to_line());
+        }
+        let object_key = hasher.finish();
+
+        let mut object = OBJECT_MAGIC.to_vec();
+        object.extend_from_slice(&(data.len() as u64).to_le_bytes());
+        object.extend_from_slice(data);
+        object.extend_from_slice(diagnostics_text.as_bytes());
+        write_atomic(&self.dir.join("objects").join(&object_key), &object)?;
+
+        manifest.retain(|entry| entry.object_key != object_key);
+        manifest.push(ManifestEntry {
+            object_key,
+            lookups,
+        });
+        if manifest.len() > MAX_MANIFEST_ENTRIES {
+            manifest.drain(..manifest.len() - MAX_MANIFEST_ENTRIES);
+        }
+        write_atomic(manifest_path, write_manifest(&manifest).as_bytes())?;
+
+        if let Some(max_size) = self.max_size {
+            self.evict(max_size)?;
+        }
+
+        Ok(())
+    }
+
+    /// Removes the least recently used files until the cache fits in `max_size`.
+    fn evict(&self, max_size: u64) -> io::Result<()> {
+        let mut files = vec![];
+        for subdir in ["manifests", "objects"].iter() {
+            for entry in fs::read_dir(self.dir.join(subdir))? {
+                let entry = entry?;
+                let metadata = entry.metadata()?;
+                let modified = metadata.modified()?;
+                files.push((modified, metadata.len(), entry.path()));
+            }
+        }
+
+        let mut size: u64 = files.iter().map(|(_, len, _)| len).sum();
+        if size <= max_size {
+            return Ok(());
+        }
+
+        files.sort();
+        for (_, len, path) in files {
+            if size <= max_size {
+                break;
+            }
+            // Another process may have removed or still be using the file
+            if fs::remove_file(&path).is_ok() {
+                size -= len;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Marks a file as recently used, to keep it from being evicted.
+fn touch(path: &Path) {
+    if let Ok(file) = fs::File::options().append(true).open(path) {
+        let _ = file.set_modified(std::time::SystemTime::now());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::include::VirtualIncludeHandler;
+    use std::time::{Duration, SystemTime};
+
+    fn storage(name: &str) -> CacheStorage {
+        let dir =
+            std::env::temp_dir().join(format!("hassle-cache-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("manifests")).unwrap();
+        fs::create_dir_all(dir.join("objects")).unwrap();
+        CacheStorage {
+            dir,
+            max_size: None,
+        }
+    }
+
+    fn record(
+        include_handler: &mut dyn DxcIncludeHandler2,
+        includes: &[(&str, Option<&str>)],
+    ) -> Vec<IncludeLookup> {
+        let mut recording_handler = RecordingIncludeHandler {
+            inner: include_handler,
+            lookups: vec![],
+            failed: false,
+        };
+        for (filename, includer) in includes {
+            let _ = recording_handler.load_include(filename, *includer);
+        }
+        recording_handler.lookups
+    }
+
+    #[test]
+    fn manifest_round_trip() {
+        let entries = vec![
+            ManifestEntry {
+                object_key: "a".to_owned(),
+                lookups: vec![
+                    IncludeLookup {
+                        filename: "./common.hlsl".to_owned(),
+                        includer: Some("main.hlsl".to_owned()),
+                        hash: Some("0123".to_owned()),
+                    },
+                    IncludeLookup {
+                        filename: "./missing.hlsl".to_owned(),
+                        includer: None,
+                        hash: None,
+                    },
+                ],
+            },
+            ManifestEntry {
+                object_key: "b".to_owned(),
+                lookups: vec![],
+            },
+        ];
+
+        assert_eq!(parse_manifest(&write_manifest(&entries)), entries);
+    }
+
+    #[test]
+    fn replay_matches_unchanged_includes() {
+        let storage = storage("replay");
+        let manifest_path = storage.dir.join("manifests").join("key");
+        let includes = [("common.hlsl", Some("main.hlsl")), ("missing.hlsl", None)];
+
+        let mut include_handler = VirtualIncludeHandler::new().file("common.hlsl", "float a;");
+        let lookups = record(&mut include_handler, &includes);
+        storage
+            .store(&manifest_path, "key", vec![], lookups, b"dxil", "")
+            .unwrap();
+        let manifest = parse_manifest(&fs::read_to_string(&manifest_path).unwrap());
+
+        let hit = storage
+            .lookup(&manifest_path, &manifest, &mut include_handler)
+            .unwrap();
+        assert_eq!(hit, Some((b"dxil".to_vec(), vec![])));
+
+        // A changed include and an include that now exists both invalidate the entry
+        let mut changed = VirtualIncludeHandler::new().file("common.hlsl", "float b;");
+        assert_eq!(
+            storage
+                .lookup(&manifest_path, &manifest, &mut changed)
+                .unwrap(),
+            None
+        );
+
+        let mut added = include_handler.clone().file("missing.hlsl", "");
+        assert_eq!(
+            storage
+                .lookup(&manifest_path, &manifest, &mut added)
+                .unwrap(),
+            None
+        );
+
+        fs::remove_dir_all(&storage.dir).unwrap();
+    }
+
+    #[test]
+    fn read_object_rejects_truncated_objects() {
+        let storage = storage("truncated");
+        let write = |key: &str, object: &[u8]| {
+            fs::write(storage.dir.join("objects").join(key), object).unwrap()
+        };
+
+        let mut object = OBJECT_MAGIC.to_vec();
+        object.extend_from_slice(&4u64.to_le_bytes());
+        object.extend_from_slice(b"dxilwarning: x");
+        write("complete", &object);
+        write("truncated_data", &object[..OBJECT_MAGIC.len() + 8 + 2]);
+        write("truncated_len", &object[..OBJECT_MAGIC.len() + 3]);
+        write("no_magic", &object[1..]);
+
+        let (data, diagnostics) = storage.read_object("complete").unwrap();
+        assert_eq!(data, b"dxil");
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(storage.read_object("truncated_data"), None);
+        assert_eq!(storage.read_object("truncated_len"), None);
+        assert_eq!(storage.read_object("no_magic"), None);
+        assert_eq!(storage.read_object("absent"), None);
+
+        fs::remove_dir_all(&storage.dir).unwrap();
+    }
+
+    #[test]
+    fn evict_removes_least_recently_used() {
+        let storage = storage("evict");
+        let now = SystemTime::now();
+
+        let set_modified = |path: &Path, age: u64| {
+            fs::File::options()
+                .append(true)
+                .open(path)
+                .unwrap()
+                .set_modified(now - Duration::from_secs(age))
+                .unwrap()
+        };
+
+        for (i, name) in ["oldest", "older", "newest"].iter().enumerate() {
+            let path = storage.dir.join("objects").join(name);
+            fs::write(&path, [0u8; 100]).unwrap();
+            set_modified(&path, 100 * (3 - i as u64));
+        }
+
+        storage.evict(300).unwrap();
+        assert!(storage.dir.join("objects").join("oldest").exists());
+
+        storage.evict(150).unwrap();
+        assert!(!storage.dir.join("objects").join("oldest").exists());
+        assert!(!storage.dir.join("objects").join("older").exists());
+        assert!(storage.dir.join("objects").join("newest").exists());
+
+        // A hit refreshes the manifest as well as the object
+        let manifest_path = storage.dir.join("manifests").join("hit");
+        storage
+            .store(&manifest_path, "hit", vec![], vec![], b"dxil", "")
+            .unwrap();
+        let manifest = parse_manifest(&fs::read_to_string(&manifest_path).unwrap());
+        let object_path = storage.dir.join("objects").join(&manifest[0].object_key);
+        set_modified(&manifest_path, 1000);
+        set_modified(&object_path, 1000);
+
+        let mut include_handler = VirtualIncludeHandler::new();
+        assert!(storage
+            .lookup(&manifest_path, &manifest, &mut include_handler)
+            .unwrap()
+            .is_some());
+
+        let hit_size =
+            fs::metadata(&manifest_path).unwrap().len() + fs::metadata(&object_path).unwrap().len();
+        storage.evict(hit_size).unwrap();
+        assert!(manifest_path.exists());
+        assert!(object_path.exists());
+        assert!(!storage.dir.join("objects").join("newest").exists());
+
+        fs::remove_dir_all(&storage.dir).unwrap();
+    }
+}
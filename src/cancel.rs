@@ -0,0 +1,129 @@
+use crate::diagnostics::Diagnostic;
+use crate::pool::CompileJob;
+use crate::utils::{HassleError, Result};
+use crate::wrapper::{Dxc, DxcIncludeHandler2};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// How often a waiting compilation checks whether it has been cancelled.
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Cancels a compilation started with [`compile_hlsl_with_cancellation()`], either
+/// explicitly or when its deadline passes.
+///
+/// Clones share the same cancellation state, so a clone can be handed to another
+/// thread to cancel the compilation from there.
+#[derive(Clone, Debug, Default)]
+pub struct CancellationToken {
+    cancelled: Arc<AtomicBool>,
+    deadline: Option<Instant>,
+}
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_deadline(mut self, deadline: Instant) -> Self {
+        self.deadline = Some(deadline);
+        self
+    }
+
+    pub fn with_timeout(self, timeout: Duration) -> Self {
+        self.with_deadline(Instant::now() + timeout)
+    }
+
+    pub fn deadline(&self) -> Option<Instant> {
+        self.deadline
+    }
+
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+
+    pub fn is_expired(&self) -> bool {
+        matches!(self.deadline, Some(deadline) if Instant::now() >= deadline)
+    }
+}
+
+/// Compiles `job` with `dxc` on a worker thread and stops waiting for it when `token`
+/// is cancelled, returning [`HassleError::Cancelled`], or when its deadline passes,
+/// returning [`HassleError::Timeout`].
+///
+/// DXC cannot be interrupted, so a compilation that is given up on keeps running in
+/// the background until the compiler returns, after which its result is dropped.
+/// The inputs are therefore passed as an owned [`CompileJob`] and include handler.
+///
+/// ```no_run
+/// use hassle_rs::{compile_hlsl_with_cancellation, CancellationToken, CompileJob, Dxc, HassleError};
+/// use std::time::Duration;
+///
+/// let dxc = Dxc::new(None).unwrap();
+/// let source = std::fs::read_to_string("unrolled.hlsl").unwrap();
+/// let job = CompileJob::new("unrolled.hlsl", source, "main", "cs_6_0");
+/// let token = CancellationToken::new().with_timeout(Duration::from_secs(30));
+///
+/// match compile_hlsl_with_cancellation(&dxc, job, None, &token) {
+///     Ok((dxil, _)) => println!("compiled {} bytes", dxil.len()),
+///     Err(HassleError::Timeout) => println!("gave up after 30 seconds"),
+///     Err(e) => panic!("{}", e),
+/// }
+/// ```
+pub fn compile_hlsl_with_cancellation(
+    dxc: &Dxc,
+    job: CompileJob,
+    include_handler: Option<Box<dyn DxcIncludeHandler2 + Send>>,
+    token: &CancellationToken,
+) -> Result<(Vec<u8>, Vec<Diagnostic>)> {
+    let (sender, receiver) = mpsc::channel();
+    let dxc = dxc.clone();
+
+    let worker = std::thread::Builder::new()
+        .name("hassle-compile".to_owned())
+        .spawn(move || {
+            let mut include_handler = include_handler;
+            let mut compile = || {
+                let compiler = dxc.create_compiler()?;
+                let library = dxc.create_library()?;
+
+                job.compile(
+                    &compiler,
+                    &library,
+                    include_handler
+                        .as_mut()
+                        .map(|h| &mut **h as &mut dyn DxcIncludeHandler2),
+                )
+            };
+            // The receiver is gone when the compilation was given up on
+            let _ = sender.send(compile());
+        })?;
+
+    loop {
+        if token.is_cancelled() {
+            return Err(HassleError::Cancelled);
+        }
+
+        let timeout = match token.deadline() {
+            Some(deadline) => match deadline.checked_duration_since(Instant::now()) {
+                Some(remaining) => remaining.min(POLL_INTERVAL),
+                None => return Err(HassleError::Timeout),
+            },
+            None => POLL_INTERVAL,
+        };
+
+        match receiver.recv_timeout(timeout) {
+            Ok(result) => return result,
+            Err(RecvTimeoutError::Timeout) => continue,
+            Err(RecvTimeoutError::Disconnected) => match worker.join() {
+                Err(panic) => std::panic::resume_unwind(panic),
+                Ok(()) => unreachable!("compile worker exited without a result"),
+            },
+        }
+    }
+}
@@ -0,0 +1,305 @@
+use crate::os::HRESULT;
+use com_rs::{iid, IUnknown, IID};
+use std::cell::RefCell;
+use std::ffi::c_void;
+use std::ptr::NonNull;
+use std::sync::atomic::{fence, AtomicU32, Ordering};
+
+pub(crate) const S_OK: HRESULT = HRESULT(0);
+pub(crate) const E_NOINTERFACE: HRESULT = HRESULT(-2_147_467_262); // 0x80004002
+pub(crate) const E_POINTER: HRESULT = HRESULT(-2_147_467_261); // 0x80004003
+pub(crate) const E_FAIL: HRESULT = HRESULT(-2_147_467_259); // 0x80004005
+
+// Defined here, as the `IID_IUnknown` symbol used by `com_rs` is not available on every platform
+iid!(
+    IID_IUnknown = 0x0000_0000,
+    0x0000,
+    0x0000,
+    0xc0,
+    0x00,
+    0x00,
+    0x00,
+    0x00,
+    0x00,
+    0x00,
+    0x46
+);
+
+/// A COM interface implemented in Rust, to be handed to DXC through [`ComObject`].
+pub(crate) trait ComClass: Sized {
+    /// `#[repr(C)]` struct with the methods of the interface, in vtable order and
+    /// without the `IUnknown` methods.
+    type Methods;
+
+    fn methods() -> Self::Methods;
+
+    /// Whether `QueryInterface` returns the object for `iid`, `IUnknown` is always supported.
+    fn implements(iid: &IID) -> bool;
+}
+
+/// `IUnknown` methods followed by the methods of the interface. On non-Windows platforms
+/// the destructor slots of [`crate::unknown::IDxcUnknownShim`] sit in between.
+#[repr(C)]
+struct ComObjectVtbl<M> {
+    query_interface: extern "system" fn(*const IUnknown, &IID, *mut *mut c_void) -> HRESULT,
+    add_ref: extern "system" fn(*const IUnknown) -> u32,
+    release: extern "system" fn(*const IUnknown) -> u32,
+    #[cfg(not(windows))]
+    complete_object_destructor: extern "system" fn(*const IUnknown),
+    #[cfg(not(windows))]
+    deleting_destructor: extern "system" fn(*const IUnknown),
+    methods: M,
+}
+
+/// Reference counted COM object wrapping `T`.
+///
+/// The allocation lives until the last reference is released, but `T` is dropped as
+/// soon as the owning [`ComObjectRef`] is, after which every method call on the object
+/// fails. This keeps borrows held by `T` from outliving the call they were passed to,
+/// even when DXC holds on to the object.
+#[repr(C)]
+pub(crate) struct ComObject<T: ComClass> {
+    vtable: Box<ComObjectVtbl<T::Methods>>,
+    refs: AtomicU32,
+    inner: RefCell<Option<T>>,
+}
+
+impl<T: ComClass> ComObject<T> {
+    /// Runs `f` on the wrapped value of `this`, for use in the methods of `T`.
+    ///
+    /// Returns `None` when the value was already dropped, or when called re-entrantly.
+    ///
+    /// # Safety
+    ///
+    /// `this` must point to a live `ComObject<T>`.
+    pub(crate) unsafe fn with_inner<R>(
+        this: *const IUnknown,
+        f: impl FnOnce(&mut T) -> R,
+    ) -> Option<R> {
+        let object = &*this.cast::<Self>();
+        let mut inner = object.inner.try_borrow_mut().ok()?;
+        inner.as_mut().map(f)
+    }
+
+    fn detach(&self) {
+        // Dropped outside of the borrow, in case dropping `T` calls back into the object
+        let inner = self
+            .inner
+            .try_borrow_mut()
+            .ok()
+            .and_then(|mut inner| inner.take());
+        drop(inner);
+    }
+
+    extern "system" fn query_interface(
+        this: *const IUnknown,
+        iid: &IID,
+        object: *mut *mut c_void,
+    ) -> HRESULT {
+        if object.is_null() {
+            return E_POINTER;
+        }
+
+        if *iid == IID_IUnknown || T::implements(iid) {
+            Self::add_ref(this);
+            unsafe { *object = this as *mut c_void };
+            S_OK
+        } else {
+            unsafe { *object = std::ptr::null_mut() };
+            E_NOINTERFACE
+        }
+    }
+
+    extern "system" fn add_ref(this: *const IUnknown) -> u32 {
+        let object = unsafe { &*this.cast::<Self>() };
+        object.refs.fetch_add(1, Ordering::Relaxed) + 1
+    }
+
+    extern "system" fn release(this: *const IUnknown) -> u32 {
+        let object = unsafe { &*this.cast::<Self>() };
+        let refs = object.refs.fetch_sub(1, Ordering::Release) - 1;
+        if refs == 0 {
+            fence(Ordering::Acquire);
+            drop(unsafe { Box::from_raw(this as *mut Self) });
+        }
+        refs
+    }
+
+    /// The allocation is owned by the reference count, so both destructors only drop
+    /// the wrapped value.
+    #[cfg(not(windows))]
+    extern "system" fn destructor(this: *const IUnknown) {
+        unsafe { &*this.cast::<Self>() }.detach();
+    }
+}
+
+/// The reference to a [`ComObject`] held by Rust, which drops the wrapped value when
+/// dropped itself.
+pub(crate) struct ComObjectRef<T: ComClass> {
+    object: NonNull<ComObject<T>>,
+}
+
+impl<T: ComClass> ComObjectRef<T> {
+    /// Allocates an object with a single reference, owned by the returned reference.
+    pub(crate) fn new(inner: T) -> Self {
+        let object = Box::new(ComObject {
+            vtable: Box::new(ComObjectVtbl {
+                query_interface: ComObject::<T>::query_interface,
+                add_ref: ComObject::<T>::add_ref,
+                release: ComObject::<T>::release,
+                #[cfg(not(windows))]
+                complete_object_destructor: ComObject::<T>::destructor,
+                #[cfg(not(windows))]
+                deleting_destructor: ComObject::<T>::destructor,
+                methods: T::methods(),
+            }),
+            refs: AtomicU32::new(1),
+            inner: RefCell::new(Some(inner)),
+        });
+
+        Self {
+            object: NonNull::from(Box::leak(object)),
+        }
+    }
+
+    /// Runs `f` on the wrapped value, see [`ComObject::with_inner()`].
+    pub(crate) fn with_inner<R>(&self, f: impl FnOnce(&mut T) -> R) -> Option<R> {
+        unsafe { ComObject::with_inner(self.as_ptr(), f) }
+    }
+
+    /// Pointer to pass to DXC as any of the interfaces implemented by `T`.
+    pub(crate) fn as_ptr<I>(&self) -> *const I {
+        self.object.as_ptr().cast()
+    }
+}
+
+impl<T: ComClass> Drop for ComObjectRef<T> {
+    fn drop(&mut self) {
+        let this = self.object.as_ptr().cast::<IUnknown>();
+        unsafe { &*self.object.as_ptr() }.detach();
+        ComObject::<T>::release(this);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::rc::Rc;
+
+    iid!(
+        IID_ITest = 0x0123_4567,
+        0x89ab,
+        0xcdef,
+        0x01,
+        0x23,
+        0x45,
+        0x67,
+        0x89,
+        0xab,
+        0xcd,
+        0xef
+    );
+    iid!(
+        IID_IOther = 0x7654_3210,
+        0xfedc,
+        0xba98,
+        0x76,
+        0x54,
+        0x32,
+        0x10,
+        0xfe,
+        0xdc,
+        0xba,
+        0x98
+    );
+
+    /// Counts how often it was dropped.
+    struct Test {
+        drops: Rc<AtomicU32>,
+    }
+
+    impl Drop for Test {
+        fn drop(&mut self) {
+            self.drops.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    impl ComClass for Test {
+        type Methods = ();
+
+        fn methods() -> Self::Methods {}
+
+        fn implements(iid: &IID) -> bool {
+            *iid == IID_ITest
+        }
+    }
+
+    fn test_object() -> (ComObjectRef<Test>, Rc<AtomicU32>) {
+        let drops = Rc::new(AtomicU32::new(0));
+        let object = ComObjectRef::new(Test {
+            drops: drops.clone(),
+        });
+        (object, drops)
+    }
+
+    #[test]
+    fn query_interface() {
+        let (object, _) = test_object();
+        let this = object.as_ptr::<IUnknown>();
+
+        for iid in [IID_IUnknown, IID_ITest].iter() {
+            let mut out = std::ptr::null_mut();
+            assert_eq!(
+                ComObject::<Test>::query_interface(this, iid, &mut out),
+                S_OK
+            );
+            assert_eq!(out as *const IUnknown, this);
+            assert_eq!(ComObject::<Test>::release(this), 1);
+        }
+
+        let mut out = this as *mut c_void;
+        assert_eq!(
+            ComObject::<Test>::query_interface(this, &IID_IOther, &mut out),
+            E_NOINTERFACE
+        );
+        assert!(out.is_null());
+
+        assert_eq!(
+            ComObject::<Test>::query_interface(this, &IID_ITest, std::ptr::null_mut()),
+            E_POINTER
+        );
+    }
+
+    #[test]
+    fn reference_count() {
+        let (object, drops) = test_object();
+        let this = object.as_ptr::<IUnknown>();
+
+        assert_eq!(ComObject::<Test>::add_ref(this), 2);
+        assert_eq!(ComObject::<Test>::add_ref(this), 3);
+        assert_eq!(ComObject::<Test>::release(this), 2);
+        assert_eq!(ComObject::<Test>::release(this), 1);
+
+        drop(object);
+        assert_eq!(drops.load(Ordering::Relaxed), 1);
+    }
+
+    #[test]
+    fn inner_dropped_with_owner() {
+        let (object, drops) = test_object();
+        let this = object.as_ptr::<IUnknown>();
+
+        assert_eq!(object.with_inner(|_| 1), Some(1));
+        // Re-entrant calls would alias the wrapped value
+        assert_eq!(object.with_inner(|_| object.with_inner(|_| 1)), Some(None));
+
+        // An outstanding reference keeps the allocation, but not the wrapped value
+        ComObject::<Test>::add_ref(this);
+        drop(object);
+        assert_eq!(drops.load(Ordering::Relaxed), 1);
+        assert_eq!(unsafe { ComObject::<Test>::with_inner(this, |_| 1) }, None);
+
+        assert_eq!(ComObject::<Test>::release(this), 0);
+        assert_eq!(drops.load(Ordering::Relaxed), 1);
+    }
+}
@@ -0,0 +1,139 @@
+use crate::include::normalize_path;
+use crate::wrapper::{DxcIncludeError, DxcIncludeHandler2, DxcIncludeSource};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/// Escapes a path for use in a Makefile rule, which Ninja depfiles share.
+fn escape_make_path(path: &Path) -> String {
+    let mut escaped = String::new();
+    for c in path.to_string_lossy().chars() {
+        match c {
+            ' ' | '#' => {
+                escaped.push('\\');
+                escaped.push(c);
+            }
+            '$' => escaped.push_str("$$"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Writes `dependencies` of `target` as a Makefile `.d` rule, the format that is also
+/// read by Ninja's `depfile`.
+///
+/// ```
+/// let mut depfile = vec![];
+/// hassle_rs::write_depfile(&mut depfile, "copy.dxil", &["copy.hlsl", "my include.hlsl"]).unwrap();
+///
+/// assert_eq!(
+///     String::from_utf8(depfile).unwrap(),
+///     "copy.dxil: \\\n  copy.hlsl \\\n  my\\ include.hlsl\n"
+/// );
+/// ```
+pub fn write_depfile<P: AsRef<Path>>(
+    mut writer: impl Write,
+    target: impl AsRef<Path>,
+    dependencies: &[P],
+) -> std::io::Result<()> {
+    write!(writer, "{}:", escape_make_path(target.as_ref()))?;
+    for dependency in dependencies {
+        write!(writer, " \\\n  {}", escape_make_path(dependency.as_ref()))?;
+    }
+    writeln!(writer)
+}
+
+/// Wraps an include handler and records every file it loads, to rebuild a shader
+/// when one of its includes changes.
+///
+/// Works with any [`DxcIncludeHandler2`] or [`crate::DxcIncludeHandler`] on every
+/// compile and preprocess call that accepts an include handler.
+///
+/// ```no_run
+/// use hassle_rs::{compile_hlsl, DependencyTracker, FileIncludeHandler};
+///
+/// let mut include_handler =
+///     DependencyTracker::new(FileIncludeHandler::new().include_dir("shaders/include"));
+///
+/// let source = std::fs::read_to_string("shaders/copy.hlsl").unwrap();
+/// let dxil = compile_hlsl(
+///     "shaders/copy.hlsl",
+///     &source,
+///     "copyCs",
+///     "cs_6_0",
+///     &[],
+///     Some(&mut include_handler),
+///     &[],
+/// )
+/// .unwrap();
+///
+/// let depfile = std::fs::File::create("copy.dxil.d").unwrap();
+/// include_handler.write_depfile(depfile, "copy.dxil").unwrap();
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct DependencyTracker<H> {
+    inner: H,
+    dependencies: Vec<PathBuf>,
+}
+
+impl<H: DxcIncludeHandler2> DependencyTracker<H> {
+    pub fn new(inner: H) -> Self {
+        Self {
+            inner,
+            dependencies: vec![],
+        }
+    }
+
+    /// Files loaded so far in include order, without duplicates. These are the paths
+    /// the files were loaded from when the wrapped handler reports them, and the
+    /// normalized paths requested by the compiler otherwise.
+    pub fn dependencies(&self) -> &[PathBuf] {
+        &self.dependencies
+    }
+
+    /// Forgets the recorded dependencies, to reuse the handler for another compilation.
+    pub fn clear(&mut self) {
+        self.dependencies.clear();
+    }
+
+    pub fn inner(&self) -> &H {
+        &self.inner
+    }
+
+    pub fn inner_mut(&mut self) -> &mut H {
+        &mut self.inner
+    }
+
+    pub fn into_inner(self) -> H {
+        self.inner
+    }
+
+    /// Writes the recorded dependencies of `target` with [`write_depfile()`].
+    pub fn write_depfile(
+        &self,
+        writer: impl Write,
+        target: impl AsRef<Path>,
+    ) -> std::io::Result<()> {
+        write_depfile(writer, target, &self.dependencies)
+    }
+}
+
+impl<H: DxcIncludeHandler2> DxcIncludeHandler2 for DependencyTracker<H> {
+    fn load_include(
+        &mut self,
+        filename: &str,
+        includer: Option<&str>,
+    ) -> Result<DxcIncludeSource, DxcIncludeError> {
+        let source = self.inner.load_include(filename, includer)?;
+
+        let path = match &source.path {
+            Some(path) => normalize_path(path),
+            None => normalize_path(Path::new(filename)),
+        };
+        if !self.dependencies.contains(&path) {
+            self.dependencies.push(path);
+        }
+
+        Ok(source)
+    }
+}
@@ -0,0 +1,217 @@
+use std::fmt;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Severity {
+    Note,
+    Warning,
+    Error,
+}
+
+impl Severity {
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Note => "note",
+            Self::Warning => "warning",
+            Self::Error => "error",
+        }
+    }
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// A single message from the compiler error buffer, such as:
+///
+/// ```text
+/// shader.hlsl:5:9: warning: implicit truncation of vector type
+///     float2 uv = input;
+///                 ^
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Diagnostic {
+    /// File the diagnostic points into, `None` for messages without a source location.
+    pub file: Option<String>,
+    /// 1-based line, `None` for messages without a source location.
+    pub line: Option<u32>,
+    /// 1-based column, `None` for messages without a source location.
+    pub column: Option<u32>,
+    pub severity: Severity,
+    pub message: String,
+    /// Source line and caret printed by the compiler below the message.
+    pub snippet: Vec<String>,
+}
+
+impl Diagnostic {
+    pub fn is_error(&self) -> bool {
+        self.severity == Severity::Error
+    }
+
+    pub fn is_warning(&self) -> bool {
+        self.severity == Severity::Warning
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(file) = &self.file {
+            write!(f, "{}:", file)?;
+            if let Some(line) = self.line {
+                write!(f, "{}:", line)?;
+                if let Some(column) = self.column {
+                    write!(f, "{}:", column)?;
+                }
+            }
+            f.write_str(" ")?;
+        }
+        write!(f, "{}: {}", self.severity, self.message)?;
+        for line in &self.snippet {
+            write!(f, "\n{}", line)?;
+        }
+        Ok(())
+    }
+}
+
+/// Splits a `file:line:column` location from the front of a header line.
+///
+/// `file` may itself contain `:`, for example in Windows paths, so the line and
+/// column are taken from the end.
+fn parse_location(location: &str) -> (Option<String>, Option<u32>, Option<u32>) {
+    let mut parts = location.rsplitn(3, ':');
+    if let (Some(column), Some(line), Some(file)) = (parts.next(), parts.next(), parts.next()) {
+        if let (Ok(line), Ok(column)) = (line.parse(), column.parse()) {
+            return (Some(file.to_owned()), Some(line), Some(column));
+        }
+    }
+
+    let mut parts = location.rsplitn(2, ':');
+    if let (Some(line), Some(file)) = (parts.next(), parts.next()) {
+        if let Ok(line) = line.parse() {
+            return (Some(file.to_owned()), Some(line), None);
+        }
+    }
+
+    (Some(location.to_owned()), None, None)
+}
+
+/// Parses a diagnostic header such as `file.hlsl:1:2: error: message` or `warning: message`.
+///
+/// The severity is taken from the first marker in the line, as the message itself may
+/// contain text like `: error: `. Lines that start with whitespace are source lines and
+/// carets printed below a header, never a header themselves.
+fn parse_header(line: &str) -> Option<Diagnostic> {
+    const SEVERITIES: [(&str, Severity); 5] = [
+        ("fatal error: ", Severity::Error),
+        ("error: ", Severity::Error),
+        ("warning: ", Severity::Warning),
+        ("note: ", Severity::Note),
+        ("remark: ", Severity::Note),
+    ];
+
+    if line.starts_with(char::is_whitespace) {
+        return None;
+    }
+
+    let (_, location, severity, message) = SEVERITIES
+        .iter()
+        .filter_map(|(marker, severity)| {
+            if let Some(message) = line.strip_prefix(marker) {
+                Some((0, None, *severity, message))
+            } else {
+                line.find(&format!(": {}", marker)).map(|idx| {
+                    (
+                        idx,
+                        Some(&line[..idx]),
+                        *severity,
+                        &line[idx + 2 + marker.len()..],
+                    )
+                })
+            }
+        })
+        .min_by_key(|(idx, ..)| *idx)?;
+
+    let (file, line, column) = match location {
+        Some(location) => parse_location(location),
+        None => (None, None, None),
+    };
+
+    Some(Diagnostic {
+        file,
+        line,
+        column,
+        severity,
+        message: message.to_owned(),
+        snippet: vec![],
+    })
+}
+
+/// Lines that are not part of any diagnostic, such as `2 warnings generated.`
+fn is_summary(line: &str) -> bool {
+    line.starts_with("In file included from ")
+        || line.ends_with(" generated.")
+        || line.trim().is_empty()
+}
+
+/// Parses the error buffer of a compilation into separate [`Diagnostic`]s.
+///
+/// ```
+/// use hassle_rs::{parse_diagnostics, Severity};
+///
+/// let diagnostics = parse_diagnostics(
+///     "shader.hlsl:3:5: error: use of undeclared identifier 'foo'\n    foo = 1;\n    ^\n",
+/// );
+///
+/// assert_eq!(diagnostics.len(), 1);
+/// assert_eq!(diagnostics[0].file.as_deref(), Some("shader.hlsl"));
+/// assert_eq!(diagnostics[0].line, Some(3));
+/// assert_eq!(diagnostics[0].column, Some(5));
+/// assert_eq!(diagnostics[0].severity, Severity::Error);
+/// assert_eq!(diagnostics[0].message, "use of undeclared identifier 'foo'");
+/// assert_eq!(diagnostics[0].snippet, ["    foo = 1;", "    ^"]);
+/// ```
+pub fn parse_diagnostics(text: &str) -> Vec<Diagnostic> {
+    let mut diagnostics: Vec<Diagnostic> = vec![];
+
+    for line in text.lines() {
+        if let Some(diagnostic) = parse_header(line) {
+            diagnostics.push(diagnostic);
+        } else if is_summary(line) {
+            continue;
+        } else if let Some(diagnostic) = diagnostics.last_mut() {
+            diagnostic.snippet.push(line.to_owned());
+        }
+    }
+
+    diagnostics
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn severity_from_first_marker() {
+        let diagnostics = parse_diagnostics("a.hlsl:1:2: warning: implicit conversion: error: x\n");
+
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].severity, Severity::Warning);
+        assert_eq!(diagnostics[0].file.as_deref(), Some("a.hlsl"));
+        assert_eq!(diagnostics[0].message, "implicit conversion: error: x");
+    }
+
+    #[test]
+    fn snippet_is_not_a_header() {
+        let diagnostics = parse_diagnostics(
+            "a.hlsl:3:5: error: unknown function\n    printf(\"x: note: y\");\n    ^\n",
+        );
+
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].severity, Severity::Error);
+        assert_eq!(
+            diagnostics[0].snippet,
+            ["    printf(\"x: note: y\");", "    ^"]
+        );
+    }
+}
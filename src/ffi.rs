@@ -1,7 +1,7 @@
 #![allow(clippy::transmute_ptr_to_ptr)]
 #![allow(clippy::too_many_arguments)]
 
-use crate::os::{HRESULT, LPCWSTR, LPWSTR};
+use crate::os::{HRESULT, LPCSTR, LPCWSTR, LPWSTR};
 pub(crate) use crate::unknown::IDxcUnknownShim;
 use com_rs::{com_interface, iid, IUnknown, IID};
 use std::ffi::c_void;
@@ -16,7 +16,24 @@ pub type DxcCreateInstanceProc2 = extern "system" fn(
     ppv: *mut *mut c_void,
 ) -> HRESULT;
 
+pub const DFCC_CONTAINER: u32 = u32::from_le_bytes([b'D', b'X', b'B', b'C']);
+pub const DFCC_RESOURCE_DEF: u32 = u32::from_le_bytes([b'R', b'D', b'E', b'F']);
+pub const DFCC_INPUT_SIGNATURE: u32 = u32::from_le_bytes([b'I', b'S', b'G', b'1']);
+pub const DFCC_OUTPUT_SIGNATURE: u32 = u32::from_le_bytes([b'O', b'S', b'G', b'1']);
+pub const DFCC_PATCH_CONSTANT_SIGNATURE: u32 = u32::from_le_bytes([b'P', b'S', b'G', b'1']);
+pub const DFCC_SHADER_STATISTICS: u32 = u32::from_le_bytes([b'S', b'T', b'A', b'T']);
+pub const DFCC_SHADER_DEBUG_INFO_DXIL: u32 = u32::from_le_bytes([b'I', b'L', b'D', b'B']);
+pub const DFCC_SHADER_DEBUG_NAME: u32 = u32::from_le_bytes([b'I', b'L', b'D', b'N']);
+pub const DFCC_FEATURE_INFO: u32 = u32::from_le_bytes([b'S', b'F', b'I', b'0']);
+pub const DFCC_PRIVATE_DATA: u32 = u32::from_le_bytes([b'P', b'R', b'I', b'V']);
+pub const DFCC_ROOT_SIGNATURE: u32 = u32::from_le_bytes([b'R', b'T', b'S', b'0']);
 pub const DFCC_DXIL: u32 = u32::from_le_bytes([b'D', b'X', b'I', b'L']);
+pub const DFCC_PIPELINE_STATE_VALIDATION: u32 = u32::from_le_bytes([b'P', b'S', b'V', b'0']);
+pub const DFCC_RUNTIME_DATA: u32 = u32::from_le_bytes([b'R', b'D', b'A', b'T']);
+pub const DFCC_SHADER_HASH: u32 = u32::from_le_bytes([b'H', b'A', b'S', b'H']);
+pub const DFCC_SHADER_SOURCE_INFO: u32 = u32::from_le_bytes([b'S', b'R', b'C', b'I']);
+pub const DFCC_SHADER_PDB_INFO: u32 = u32::from_le_bytes([b'P', b'D', b'B', b'I']);
+pub const DFCC_COMPILER_VERSION: u32 = u32::from_le_bytes([b'V', b'E', b'R', b'S']);
 
 iid!(pub IID_IDxcBlob = 0x8BA5_FB08, 0x5195, 0x40e2, 0xAC, 0x58, 0x0D, 0x98, 0x9C, 0x3A, 0x01, 0x02);
 com_interface! {
@@ -137,6 +154,93 @@ com_interface! {
     }
 }
 
+iid!(pub IID_IDxcBlobUtf16 = 0xA3F8_4EAB, 0x0FAA, 0x497E, 0xA3, 0x9C, 0xEE, 0x6E, 0xD6, 0x0B, 0x2D, 0x84);
+com_interface! {
+    interface IDxcBlobUtf16: IDxcBlobEncoding, IDxcBlob, IDxcUnknownShim, IUnknown {
+        iid: IID_IDxcBlobUtf16,
+        vtable: IDxcBlobUtf16Vtbl,
+        fn get_string_pointer() -> LPCWSTR;
+        fn get_string_length() -> usize;
+    }
+}
+
+iid!(pub IID_IDxcBlobUtf8 = 0x3DA6_36C9, 0xBA71, 0x4024, 0xA3, 0x01, 0x30, 0xCB, 0xF1, 0x25, 0x30, 0x5B);
+com_interface! {
+    interface IDxcBlobUtf8: IDxcBlobEncoding, IDxcBlob, IDxcUnknownShim, IUnknown {
+        iid: IID_IDxcBlobUtf8,
+        vtable: IDxcBlobUtf8Vtbl,
+        fn get_string_pointer() -> LPCSTR;
+        fn get_string_length() -> usize;
+    }
+}
+
+pub const DXC_OUT_NONE: u32 = 0;
+pub const DXC_OUT_OBJECT: u32 = 1; // IDxcBlob - Shader or library object
+pub const DXC_OUT_ERRORS: u32 = 2; // IDxcBlobUtf8 or IDxcBlobUtf16
+pub const DXC_OUT_PDB: u32 = 3; // IDxcBlob
+pub const DXC_OUT_SHADER_HASH: u32 = 4; // IDxcBlob - DxcShaderHash of shader or shader with source info
+pub const DXC_OUT_DISASSEMBLY: u32 = 5; // IDxcBlobUtf8 or IDxcBlobUtf16 - from Disassemble
+pub const DXC_OUT_HLSL: u32 = 6; // IDxcBlobUtf8 or IDxcBlobUtf16 - from Preprocessor or Rewriter
+pub const DXC_OUT_TEXT: u32 = 7; // IDxcBlobUtf8 or IDxcBlobUtf16 - other text, such as -ast-dump or -Odump
+pub const DXC_OUT_REFLECTION: u32 = 8; // IDxcBlob - RDAT part with reflection data
+pub const DXC_OUT_ROOT_SIGNATURE: u32 = 9; // IDxcBlob - Serialized root signature output
+pub const DXC_OUT_EXTRA_OUTPUTS: u32 = 10; // IDxcExtraOutputs - Extra outputs
+
+iid!(pub IID_IDxcResult = 0x5834_6CDA, 0xDDE7, 0x4497, 0x94, 0x61, 0x6F, 0x87, 0xAF, 0x5E, 0x06, 0x59);
+com_interface! {
+    interface IDxcResult: IDxcOperationResult, IDxcUnknownShim, IUnknown {
+        iid: IID_IDxcResult,
+        vtable: IDxcResultVtbl,
+        fn has_output(dxc_out_kind: u32) -> i32;
+        fn get_output(
+            dxc_out_kind: u32,
+            iid: &IID,
+            object: *mut *mut c_void,
+            output_name: *mut *mut IDxcBlobUtf16) -> HRESULT;
+        fn get_num_outputs() -> u32;
+        fn get_output_by_index(index: u32) -> u32;
+        fn primary_output() -> u32;
+    }
+}
+
+pub const DXC_HASHFLAG_INCLUDES_SOURCE: u32 = 1; // This flag indicates that the shader hash was computed taking into account source information (-Zss)
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct DxcShaderHash {
+    pub flags: u32,
+    pub hash_digest: [u8; 16],
+}
+
+#[repr(C)]
+#[derive(Debug)]
+pub struct DxcBuffer {
+    pub ptr: *const c_void,
+    pub size: usize,
+    pub encoding: u32,
+}
+
+iid!(pub IID_IDxcCompiler3 = 0x228B_4687, 0x5A6A, 0x4730, 0x90, 0x0C, 0x97, 0x02, 0xB2, 0x20, 0x3F, 0x54);
+com_interface! {
+    interface IDxcCompiler3: IDxcUnknownShim, IUnknown {
+        iid: IID_IDxcCompiler3,
+        vtable: IDxcCompiler3Vtbl,
+
+        fn compile(
+            source: *const DxcBuffer,
+            arguments: *const LPCWSTR,
+            arg_count: u32,
+            include_handler: *const IDxcIncludeHandler,
+            iid: &IID,
+            result: *mut *mut c_void) -> HRESULT;
+
+        fn disassemble(
+            object: *const DxcBuffer,
+            iid: &IID,
+            result: *mut *mut c_void) -> HRESULT;
+    }
+}
+
 iid!(pub IID_IDxcLinker = 0xF1B5_BE2A, 0x62DD, 0x4327, 0xA1, 0xC2, 0x42, 0xAC, 0x1E, 0x1E, 0x78, 0xE6);
 com_interface! {
     interface IDxcLinker: IDxcUnknownShim, IUnknown {
@@ -258,7 +362,7 @@ com_interface! {
 
 iid!(pub IID_IDxcVersionInfo2 = 0xfb69_04c4, 0x42f0, 0x4b62, 0x9c, 0x46, 0x98, 0x3a, 0xf7, 0xda, 0x7c, 0x83);
 com_interface! {
-    interface IDxcVersionInfo2: IDxcUnknownShim, IUnknown {
+    interface IDxcVersionInfo2: IDxcVersionInfo, IDxcUnknownShim, IUnknown {
         iid: IID_IDxcVersionInfo2,
         vtable: IDxcVersionInfo2Vtbl,
 
@@ -0,0 +1,540 @@
+use crate::wrapper::{DxcIncludeEncoding, DxcIncludeError, DxcIncludeHandler2, DxcIncludeSource};
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::path::{Component, Path, PathBuf};
+
+/// Order in which [`FileIncludeHandler`] looks up includes.
+///
+/// DXC does not tell the include handler whether an `#include` used quotes or angle
+/// brackets, so the order applies to every include.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum IncludeStyle {
+    /// Like `#include "file"`: relative to the including file first, then the include directories.
+    #[default]
+    Quoted,
+    /// Like `#include <file>`: the include directories first, then relative to the including file.
+    System,
+}
+
+/// Removes `.` components and folds `..` into its parent where possible,
+/// without touching the filesystem.
+pub(crate) fn normalize_path(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                if matches!(
+                    normalized.components().next_back(),
+                    Some(Component::Normal(_))
+                ) {
+                    normalized.pop();
+                } else {
+                    normalized.push(component);
+                }
+            }
+            _ => normalized.push(component),
+        }
+    }
+    normalized
+}
+
+/// Paths of all `#include "file"` and `#include <file>` directives in `source`,
+/// including those in comments and inactive preprocessor branches.
+pub(crate) fn include_directives(source: &str) -> impl Iterator<Item = &str> {
+    source.lines().filter_map(|line| {
+        let directive = line.trim_start().strip_prefix('#')?;
+        let path = directive.trim_start().strip_prefix("include")?.trim_start();
+        let close = match path.chars().next()? {
+            '"' => '"',
+            '<' => '>',
+            _ => return None,
+        };
+        let path = &path[1..];
+        path.find(close).map(|end| &path[..end])
+    })
+}
+
+/// Remembers the `#include` directives of every loaded file, to find out which file
+/// includes a path passed by DXC.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct IncludeTracker {
+    /// Paths that DXC passes for the includer-relative lookup of every `#include` seen so
+    /// far, with the file that contains it.
+    includes: Vec<(String, PathBuf)>,
+    /// Normalized `#include` paths as spelled, with the file that contains them.
+    spellings: Vec<(String, PathBuf)>,
+}
+
+impl IncludeTracker {
+    /// Records the includes in `source`, the contents of `includer` as DXC names it.
+    pub(crate) fn track(&mut self, includer: &str, source: &str) {
+        let includer_dir = Path::new(includer).parent().unwrap_or(Path::new(""));
+        for include in include_directives(source) {
+            self.includes.push((
+                includer.to_owned(),
+                normalize_path(&includer_dir.join(include)),
+            ));
+            self.spellings
+                .push((includer.to_owned(), normalize_path(Path::new(include))));
+        }
+    }
+
+    /// The file that includes `filename`, or `None` when no file or more than one file
+    /// has a matching `#include`.
+    ///
+    /// `filename` matches an include relative to its includer exactly. Includes found
+    /// through an include directory can only be matched by the end of the path.
+    pub(crate) fn find_includer(&self, filename: &str) -> Option<String> {
+        let filename = normalize_path(Path::new(filename));
+
+        let relative = Self::unique_includer(
+            self.includes
+                .iter()
+                .filter(|(_, include)| *include == filename),
+        );
+        match relative {
+            Ok(Some(includer)) => Some(includer),
+            Ok(None) => Self::unique_includer(
+                self.spellings
+                    .iter()
+                    .filter(|(_, include)| filename.ends_with(include)),
+            )
+            .ok()
+            .flatten(),
+            Err(()) => None,
+        }
+    }
+
+    /// `Err` when the matches have different includers.
+    fn unique_includer<'a>(
+        mut matches: impl Iterator<Item = &'a (String, PathBuf)>,
+    ) -> Result<Option<String>, ()> {
+        let includer = match matches.next() {
+            Some((includer, _)) => includer,
+            None => return Ok(None),
+        };
+        if matches.all(|(other, _)| other == includer) {
+            Ok(Some(includer.clone()))
+        } else {
+            Err(())
+        }
+    }
+}
+
+/// Include handler that loads files from disk, searching an ordered list of
+/// include directories like `-I`.
+///
+/// DXC passes includes to the handler joined with the directory of the including
+/// file as it knows it. The handler remembers where each file was actually found,
+/// so that includes are resolved relative to the real location of their includer
+/// even when it was found through an include directory.
+///
+/// ```no_run
+/// use hassle_rs::{Dxc, FileIncludeHandler, IncludeStyle};
+///
+/// let mut include_handler = FileIncludeHandler::new()
+///     .source_file("shaders/copy.hlsl")
+///     .include_dir("engine/shaders/include")
+///     .style(IncludeStyle::Quoted);
+///
+/// let dxc = Dxc::new(None).unwrap();
+/// let compiler = dxc.create_compiler().unwrap();
+/// let library = dxc.create_library().unwrap();
+/// let source = std::fs::read_to_string("shaders/copy.hlsl").unwrap();
+/// let blob = library.create_blob_with_encoding_from_str(&source).unwrap();
+///
+/// let result = compiler.compile(
+///     &blob,
+///     "shaders/copy.hlsl",
+///     "copyCs",
+///     "cs_6_0",
+///     &[],
+///     Some(&mut include_handler),
+///     &[],
+/// );
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct FileIncludeHandler {
+    include_dirs: Vec<PathBuf>,
+    style: IncludeStyle,
+    /// Directories as DXC spells them, and where their files were actually found.
+    resolved_dirs: Vec<(PathBuf, PathBuf)>,
+}
+
+impl FileIncludeHandler {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Appends a directory to search for includes, directories are searched in the
+    /// order they are added.
+    pub fn include_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.include_dirs.push(dir.into());
+        self
+    }
+
+    pub fn include_dirs<I>(mut self, dirs: I) -> Self
+    where
+        I: IntoIterator,
+        I::Item: Into<PathBuf>,
+    {
+        self.include_dirs.extend(dirs.into_iter().map(Into::into));
+        self
+    }
+
+    pub fn style(mut self, style: IncludeStyle) -> Self {
+        self.style = style;
+        self
+    }
+
+    /// Registers the `source_name` passed to the compiler, so that includes from the
+    /// main file can be matched against the include directories.
+    pub fn source_file(mut self, source_name: impl AsRef<Path>) -> Self {
+        let source_dir = normalize_path(source_name.as_ref().parent().unwrap_or(Path::new("")));
+        self.record_dir(source_dir.clone(), source_dir);
+        self
+    }
+
+    fn record_dir(&mut self, spelled_dir: PathBuf, resolved_dir: PathBuf) {
+        if !self
+            .resolved_dirs
+            .iter()
+            .any(|(spelled, _)| *spelled == spelled_dir)
+        {
+            self.resolved_dirs.push((spelled_dir, resolved_dir));
+        }
+    }
+
+    fn candidates(&self, requested: &Path) -> Vec<PathBuf> {
+        // Relative to the including file, where DXC thinks it is and where it was found
+        let mut includer_relative = vec![requested.to_owned()];
+        // The include as spelled in the source, without the directory of the includer
+        let mut spellings = vec![];
+
+        for (spelled_dir, resolved_dir) in self.resolved_dirs.iter().rev() {
+            if let Ok(rest) = requested.strip_prefix(spelled_dir) {
+                includer_relative.push(resolved_dir.join(rest));
+                spellings.push(rest.to_owned());
+            }
+        }
+        // An absolute path outside of any known directory is not searched for
+        if spellings.is_empty() && requested.is_relative() {
+            spellings.push(requested.to_owned());
+        }
+
+        let in_include_dirs = self
+            .include_dirs
+            .iter()
+            .flat_map(|dir| spellings.iter().map(move |spelling| dir.join(spelling)));
+
+        match self.style {
+            IncludeStyle::Quoted => includer_relative
+                .into_iter()
+                .chain(in_include_dirs)
+                .collect(),
+            IncludeStyle::System => in_include_dirs.chain(includer_relative).collect(),
+        }
+    }
+}
+
+impl DxcIncludeHandler2 for FileIncludeHandler {
+    fn load_include(
+        &mut self,
+        filename: &str,
+        _includer: Option<&str>,
+    ) -> Result<DxcIncludeSource, DxcIncludeError> {
+        let requested = normalize_path(Path::new(filename));
+
+        for candidate in self.candidates(&requested) {
+            let data = match std::fs::read(&candidate) {
+                Ok(data) => data,
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+                Err(e) => {
+                    return Err(DxcIncludeError::Failed(format!(
+                        "failed to read {}: {}",
+                        candidate.display(),
+                        e
+                    )))
+                }
+            };
+
+            if let (Some(spelled_dir), Some(resolved_dir)) =
+                (requested.parent(), candidate.parent())
+            {
+                self.record_dir(spelled_dir.to_owned(), resolved_dir.to_owned());
+            }
+            return Ok(
+                DxcIncludeSource::new(data, DxcIncludeEncoding::Unknown).with_path(candidate)
+            );
+        }
+
+        Err(DxcIncludeError::NotFound)
+    }
+}
+
+/// Include handler that serves files from memory instead of from disk, such as
+/// sources stored in an asset database or embedded in the binary.
+///
+/// Files are stored in layers. Every [`VirtualIncludeHandler::overlay()`] adds a new
+/// layer that shadows files with the same path in the layers below it, which allows
+/// patching a set of base sources without modifying them.
+///
+/// Paths are normalized before lookup and are resolved relative to the including
+/// file, like DXC does on disk, so the `source_name` passed to the compiler should
+/// be a path in the same virtual filesystem.
+///
+/// ```
+/// use hassle_rs::VirtualIncludeHandler;
+///
+/// const BASE: &[(&str, &str)] = &[
+///     ("shaders/common.hlsl", "#define QUALITY 1"),
+///     ("shaders/lighting.hlsl", "float3 shade() { return 0; }"),
+/// ];
+///
+/// let include_handler = VirtualIncludeHandler::new()
+///     .files(BASE.iter().copied())
+///     .overlay("shaders", [("common.hlsl", "#define QUALITY 2")]);
+///
+/// assert_eq!(
+///     include_handler.get("./shaders/common.hlsl"),
+///     Some("#define QUALITY 2")
+/// );
+/// assert!(include_handler
+///     .get("shaders/../shaders/lighting.hlsl")
+///     .is_some());
+/// ```
+#[derive(Clone, Debug)]
+pub struct VirtualIncludeHandler {
+    /// Layers from bottom to top, keyed by normalized path.
+    layers: Vec<HashMap<PathBuf, Cow<'static, str>>>,
+}
+
+impl Default for VirtualIncludeHandler {
+    fn default() -> Self {
+        Self {
+            layers: vec![HashMap::new()],
+        }
+    }
+}
+
+impl VirtualIncludeHandler {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Builder variant of [`VirtualIncludeHandler::insert()`].
+    pub fn file(mut self, path: impl AsRef<Path>, source: impl Into<Cow<'static, str>>) -> Self {
+        self.insert(path, source);
+        self
+    }
+
+    /// Adds files to the topmost layer, for example from a table of embedded
+    /// `(&'static str, &'static str)` pairs, which are stored without copying.
+    pub fn files<I, P, S>(mut self, files: I) -> Self
+    where
+        I: IntoIterator<Item = (P, S)>,
+        P: AsRef<Path>,
+        S: Into<Cow<'static, str>>,
+    {
+        for (path, source) in files {
+            self.insert(path, source);
+        }
+        self
+    }
+
+    /// Adds a new layer with `files` placed under `dir`, shadowing files with the
+    /// same path in all previous layers.
+    pub fn overlay<I, P, S>(mut self, dir: impl AsRef<Path>, files: I) -> Self
+    where
+        I: IntoIterator<Item = (P, S)>,
+        P: AsRef<Path>,
+        S: Into<Cow<'static, str>>,
+    {
+        let layer = files
+            .into_iter()
+            .map(|(path, source)| (normalize_path(&dir.as_ref().join(path)), source.into()))
+            .collect();
+        self.layers.push(layer);
+        self
+    }
+
+    /// Adds a file to the topmost layer, replacing a file with the same path in that layer.
+    pub fn insert(&mut self, path: impl AsRef<Path>, source: impl Into<Cow<'static, str>>) {
+        self.layers
+            .last_mut()
+            .unwrap()
+            .insert(normalize_path(path.as_ref()), source.into());
+    }
+
+    /// Returns the contents of `path` from the topmost layer that contains it.
+    pub fn get(&self, path: impl AsRef<Path>) -> Option<&str> {
+        let path = normalize_path(path.as_ref());
+        self.layers
+            .iter()
+            .rev()
+            .find_map(|layer| layer.get(&path))
+            .map(AsRef::as_ref)
+    }
+}
+
+impl DxcIncludeHandler2 for VirtualIncludeHandler {
+    fn load_include(
+        &mut self,
+        filename: &str,
+        _includer: Option<&str>,
+    ) -> Result<DxcIncludeSource, DxcIncludeError> {
+        self.get(filename)
+            .map(DxcIncludeSource::from)
+            .ok_or(DxcIncludeError::NotFound)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    /// Creates a temporary directory tree with `files`, where a name ending in `/` is
+    /// an empty directory.
+    fn tree(name: &str, files: &[(&str, &str)]) -> PathBuf {
+        let root =
+            std::env::temp_dir().join(format!("hassle-include-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        for (path, contents) in files {
+            let path = root.join(path);
+            if path.to_string_lossy().ends_with('/') {
+                fs::create_dir_all(&path).unwrap();
+            } else {
+                fs::create_dir_all(path.parent().unwrap()).unwrap();
+                fs::write(&path, contents).unwrap();
+            }
+        }
+        root
+    }
+
+    fn load(handler: &mut FileIncludeHandler, filename: &Path) -> (PathBuf, String) {
+        let source = handler
+            .load_include(filename.to_str().unwrap(), None)
+            .unwrap();
+        (
+            source.path.unwrap(),
+            String::from_utf8(source.data).unwrap(),
+        )
+    }
+
+    #[test]
+    fn includer_relative_after_include_dir() {
+        let root = tree(
+            "includer-relative",
+            &[
+                ("shaders/main.hlsl", "#include \"engine/lighting.hlsl\""),
+                ("include/engine/lighting.hlsl", "#include \"brdf.hlsl\""),
+                ("include/engine/brdf.hlsl", "engine brdf"),
+                ("include/brdf.hlsl", "top-level brdf"),
+            ],
+        );
+        let mut handler = FileIncludeHandler::new()
+            .source_file(root.join("shaders/main.hlsl"))
+            .include_dir(root.join("include"));
+
+        // DXC joins the include with the directory it believes the includer is in
+        let (path, _) = load(&mut handler, &root.join("shaders/engine/lighting.hlsl"));
+        assert_eq!(path, root.join("include/engine/lighting.hlsl"));
+
+        let (path, data) = load(&mut handler, &root.join("shaders/engine/brdf.hlsl"));
+        assert_eq!(path, root.join("include/engine/brdf.hlsl"));
+        assert_eq!(data, "engine brdf");
+    }
+
+    #[test]
+    fn search_order() {
+        let root = tree(
+            "search-order",
+            &[
+                ("shaders/main.hlsl", "#include \"common.hlsl\""),
+                ("shaders/common.hlsl", "local"),
+                ("include/common.hlsl", "include dir"),
+            ],
+        );
+        let requested = root.join("shaders/common.hlsl");
+        let handler = FileIncludeHandler::new()
+            .source_file(root.join("shaders/main.hlsl"))
+            .include_dir(root.join("include"));
+
+        let mut quoted = handler.clone().style(IncludeStyle::Quoted);
+        assert_eq!(load(&mut quoted, &requested).1, "local");
+
+        let mut system = handler.style(IncludeStyle::System);
+        assert_eq!(load(&mut system, &requested).1, "include dir");
+    }
+
+    #[test]
+    fn unreadable_include_fails() {
+        let root = tree(
+            "unreadable",
+            &[
+                ("shaders/main.hlsl", "#include \"common.hlsl\""),
+                ("shaders/common.hlsl/", ""),
+            ],
+        );
+        let mut handler = FileIncludeHandler::new().source_file(root.join("shaders/main.hlsl"));
+
+        let requested = root.join("shaders/common.hlsl");
+        match handler.load_include(requested.to_str().unwrap(), None) {
+            Err(DxcIncludeError::Failed(message)) => {
+                assert!(message.contains("common.hlsl"), "{}", message)
+            }
+            other => panic!("expected a failure, got {:?}", other.map(|s| s.path)),
+        }
+        assert!(matches!(
+            handler.load_include(root.join("shaders/missing.hlsl").to_str().unwrap(), None),
+            Err(DxcIncludeError::NotFound)
+        ));
+    }
+
+    #[test]
+    fn includer_of_relative_include() {
+        let mut tracker = IncludeTracker::default();
+        tracker.track("a/x.hlsl", "#include \"common.hlsl\"");
+        tracker.track("b/y.hlsl", "#include \"common.hlsl\"");
+
+        assert_eq!(
+            tracker.find_includer("./a/common.hlsl").as_deref(),
+            Some("a/x.hlsl")
+        );
+        assert_eq!(
+            tracker.find_includer("b/common.hlsl").as_deref(),
+            Some("b/y.hlsl")
+        );
+        // Found through an include directory, either file could have included it
+        assert_eq!(tracker.find_includer("include/common.hlsl"), None);
+        assert_eq!(tracker.find_includer("./a/other.hlsl"), None);
+    }
+
+    #[test]
+    fn includer_of_parent_include() {
+        let mut tracker = IncludeTracker::default();
+        tracker.track("shaders/sub/main.hlsl", "#include \"../common.hlsl\"");
+
+        assert_eq!(
+            tracker
+                .find_includer("./shaders/sub/../common.hlsl")
+                .as_deref(),
+            Some("shaders/sub/main.hlsl")
+        );
+    }
+
+    #[test]
+    fn includer_through_include_dir() {
+        let mut tracker = IncludeTracker::default();
+        tracker.track("main.hlsl", "#include <engine/lighting.hlsl>");
+
+        assert_eq!(
+            tracker
+                .find_includer("include/engine/lighting.hlsl")
+                .as_deref(),
+            Some("main.hlsl")
+        );
+    }
+}
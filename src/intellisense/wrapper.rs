@@ -3,16 +3,19 @@ use crate::os::{CoTaskMemFree, BSTR, LPSTR};
 use crate::utils::Result;
 use crate::wrapper::Dxc;
 use com_rs::ComPtr;
+use libloading::Library;
 use std::ffi::CString;
+use std::sync::Arc;
 
 #[derive(Debug)]
 pub struct DxcIntellisense {
     inner: ComPtr<IDxcIntelliSense>,
+    lib: Arc<Library>,
 }
 
 impl DxcIntellisense {
-    fn new(inner: ComPtr<IDxcIntelliSense>) -> Self {
-        Self { inner }
+    fn new(inner: ComPtr<IDxcIntelliSense>, lib: Arc<Library>) -> Self {
+        Self { inner, lib }
     }
 
     pub fn get_default_editing_tu_options(&self) -> Result<DxcTranslationUnitFlags> {
@@ -24,7 +27,7 @@ impl DxcIntellisense {
     pub fn create_index(&self) -> Result<DxcIndex> {
         let mut index: ComPtr<IDxcIndex> = ComPtr::new();
         unsafe { self.inner.create_index(index.as_mut_ptr()) }.result()?;
-        Ok(DxcIndex::new(index))
+        Ok(DxcIndex::new(index, self.lib.clone()))
     }
 
     pub fn create_unsaved_file(&self, file_name: &str, contents: &str) -> Result<DxcUnsavedFile> {
@@ -41,18 +44,19 @@ impl DxcIntellisense {
             )
         }
         .result()?;
-        Ok(DxcUnsavedFile::new(file))
+        Ok(DxcUnsavedFile::new(file, self.lib.clone()))
     }
 }
 
 #[derive(Debug)]
 pub struct DxcIndex {
     inner: ComPtr<IDxcIndex>,
+    lib: Arc<Library>,
 }
 
 impl DxcIndex {
-    fn new(inner: ComPtr<IDxcIndex>) -> Self {
-        Self { inner }
+    fn new(inner: ComPtr<IDxcIndex>, lib: Arc<Library>) -> Self {
+        Self { inner, lib }
     }
 }
 
@@ -96,13 +100,14 @@ impl DxcIndex {
             )
         }
         .result()?;
-        Ok(DxcTranslationUnit::new(tu))
+        Ok(DxcTranslationUnit::new(tu, self.lib.clone()))
     }
 }
 
 #[derive(Debug)]
 pub struct DxcUnsavedFile {
     inner: ComPtr<IDxcUnsavedFile>,
+    lib: Arc<Library>,
 }
 
 impl DxcUnsavedFile {
@@ -111,42 +116,44 @@ impl DxcUnsavedFile {
         unsafe { self.inner.get_length(&mut length) }.result_with_success(length)
     }
 
-    fn new(inner: ComPtr<IDxcUnsavedFile>) -> Self {
-        DxcUnsavedFile { inner }
+    fn new(inner: ComPtr<IDxcUnsavedFile>, lib: Arc<Library>) -> Self {
+        Self { inner, lib }
     }
 }
 
 #[derive(Debug)]
 pub struct DxcTranslationUnit {
     inner: ComPtr<IDxcTranslationUnit>,
+    lib: Arc<Library>,
 }
 
 impl DxcTranslationUnit {
-    fn new(inner: ComPtr<IDxcTranslationUnit>) -> Self {
-        DxcTranslationUnit { inner }
+    fn new(inner: ComPtr<IDxcTranslationUnit>, lib: Arc<Library>) -> Self {
+        Self { inner, lib }
     }
 
     pub fn get_file(&self, name: &[u8]) -> Result<DxcFile> {
         let mut file: ComPtr<IDxcFile> = ComPtr::new();
         unsafe { self.inner.get_file(name.as_ptr(), file.as_mut_ptr()) }.result()?;
-        Ok(DxcFile::new(file))
+        Ok(DxcFile::new(file, self.lib.clone()))
     }
 
     pub fn get_cursor(&self) -> Result<DxcCursor> {
         let mut cursor: ComPtr<IDxcCursor> = ComPtr::new();
         unsafe { self.inner.get_cursor(cursor.as_mut_ptr()) }.result()?;
-        Ok(DxcCursor::new(cursor))
+        Ok(DxcCursor::new(cursor, self.lib.clone()))
     }
 }
 
 #[derive(Debug)]
 pub struct DxcCursor {
     inner: ComPtr<IDxcCursor>,
+    lib: Arc<Library>,
 }
 
 impl DxcCursor {
-    fn new(inner: ComPtr<IDxcCursor>) -> Self {
-        DxcCursor { inner }
+    fn new(inner: ComPtr<IDxcCursor>, lib: Arc<Library>) -> Self {
+        Self { inner, lib }
     }
 
     pub fn get_children(&self, skip: u32, max_count: u32) -> Result<Vec<DxcCursor>> {
@@ -165,7 +172,7 @@ impl DxcCursor {
             .map(|&ptr| {
                 let mut childcursor = ComPtr::<IDxcCursor>::new();
                 *childcursor.as_mut_ptr() = ptr;
-                DxcCursor::new(childcursor)
+                DxcCursor::new(childcursor, self.lib.clone())
             })
             .collect::<Vec<_>>();
         unsafe { CoTaskMemFree(result.cast()) };
@@ -189,13 +196,13 @@ impl DxcCursor {
     pub fn get_extent(&self) -> Result<DxcSourceRange> {
         let mut range: ComPtr<IDxcSourceRange> = ComPtr::new();
         unsafe { self.inner.get_extent(range.as_mut_ptr()) }.result()?;
-        Ok(DxcSourceRange::new(range))
+        Ok(DxcSourceRange::new(range, self.lib.clone()))
     }
 
     pub fn get_location(&self) -> Result<DxcSourceLocation> {
         let mut location: ComPtr<IDxcSourceLocation> = ComPtr::new();
         unsafe { self.inner.get_location(location.as_mut_ptr()) }.result()?;
-        Ok(DxcSourceLocation::new(location))
+        Ok(DxcSourceLocation::new(location, self.lib.clone()))
     }
 
     pub fn get_display_name(&self) -> Result<String> {
@@ -234,19 +241,19 @@ impl DxcCursor {
     pub fn get_semantic_parent(&self) -> Result<DxcCursor> {
         let mut inner = ComPtr::<IDxcCursor>::new();
         unsafe { self.inner.get_semantic_parent(inner.as_mut_ptr()) }.result()?;
-        Ok(DxcCursor::new(inner))
+        Ok(DxcCursor::new(inner, self.lib.clone()))
     }
 
     pub fn get_lexical_parent(&self) -> Result<DxcCursor> {
         let mut inner = ComPtr::<IDxcCursor>::new();
         unsafe { self.inner.get_lexical_parent(inner.as_mut_ptr()) }.result()?;
-        Ok(DxcCursor::new(inner))
+        Ok(DxcCursor::new(inner, self.lib.clone()))
     }
 
     pub fn get_cursor_type(&self) -> Result<DxcType> {
         let mut inner = ComPtr::<IDxcType>::new();
         unsafe { self.inner.get_cursor_type(inner.as_mut_ptr()) }.result()?;
-        Ok(DxcType::new(inner))
+        Ok(DxcType::new(inner, self.lib.clone()))
     }
 
     pub fn get_num_arguments(&self) -> Result<i32> {
@@ -258,19 +265,19 @@ impl DxcCursor {
     pub fn get_argument_at(&self, index: i32) -> Result<DxcCursor> {
         let mut inner = ComPtr::<IDxcCursor>::new();
         unsafe { self.inner.get_argument_at(index, inner.as_mut_ptr()) }.result()?;
-        Ok(DxcCursor::new(inner))
+        Ok(DxcCursor::new(inner, self.lib.clone()))
     }
 
     pub fn get_referenced_cursor(&self) -> Result<DxcCursor> {
         let mut inner = ComPtr::<IDxcCursor>::new();
         unsafe { self.inner.get_referenced_cursor(inner.as_mut_ptr()) }.result()?;
-        Ok(DxcCursor::new(inner))
+        Ok(DxcCursor::new(inner, self.lib.clone()))
     }
 
     pub fn get_definition_cursor(&self) -> Result<DxcCursor> {
         let mut inner = ComPtr::<IDxcCursor>::new();
         unsafe { self.inner.get_definition_cursor(inner.as_mut_ptr()) }.result()?;
-        Ok(DxcCursor::new(inner))
+        Ok(DxcCursor::new(inner, self.lib.clone()))
     }
 
     pub fn find_references_in_file(
@@ -299,7 +306,7 @@ impl DxcCursor {
             .map(|&ptr| {
                 let mut childcursor = ComPtr::<IDxcCursor>::new();
                 *childcursor.as_mut_ptr() = ptr;
-                DxcCursor::new(childcursor)
+                DxcCursor::new(childcursor, self.lib.clone())
             })
             .collect::<Vec<_>>();
         unsafe { CoTaskMemFree(result.cast()) };
@@ -335,7 +342,7 @@ impl DxcCursor {
                 .get_snapped_child(location.inner.as_ptr(), inner.as_mut_ptr())
         }
         .result()?;
-        Ok(DxcCursor::new(inner))
+        Ok(DxcCursor::new(inner, self.lib.clone()))
     }
 
     pub fn get_source<'a>(&self, source: &'a str) -> Result<&'a str> {
@@ -355,11 +362,12 @@ impl DxcCursor {
 #[derive(Debug)]
 pub struct DxcType {
     inner: ComPtr<IDxcType>,
+    lib: Arc<Library>,
 }
 
 impl DxcType {
-    fn new(inner: ComPtr<IDxcType>) -> Self {
-        DxcType { inner }
+    fn new(inner: ComPtr<IDxcType>, lib: Arc<Library>) -> Self {
+        Self { inner, lib }
     }
 
     pub fn get_spelling(&self) -> Result<String> {
@@ -372,11 +380,12 @@ impl DxcType {
 #[derive(Debug)]
 pub struct DxcSourceLocation {
     inner: ComPtr<IDxcSourceLocation>,
+    lib: Arc<Library>,
 }
 
 impl DxcSourceLocation {
-    fn new(inner: ComPtr<IDxcSourceLocation>) -> Self {
-        DxcSourceLocation { inner }
+    fn new(inner: ComPtr<IDxcSourceLocation>, lib: Arc<Library>) -> Self {
+        Self { inner, lib }
     }
 }
 
@@ -389,6 +398,7 @@ pub struct DxcSourceOffsets {
 #[derive(Debug)]
 pub struct DxcSourceRange {
     inner: ComPtr<IDxcSourceRange>,
+    lib: Arc<Library>,
 }
 
 impl DxcSourceRange {
@@ -405,19 +415,20 @@ impl DxcSourceRange {
 }
 
 impl DxcSourceRange {
-    fn new(inner: ComPtr<IDxcSourceRange>) -> Self {
-        DxcSourceRange { inner }
+    fn new(inner: ComPtr<IDxcSourceRange>, lib: Arc<Library>) -> Self {
+        Self { inner, lib }
     }
 }
 
 #[derive(Debug)]
 pub struct DxcFile {
     inner: ComPtr<IDxcFile>,
+    lib: Arc<Library>,
 }
 
 impl DxcFile {
-    fn new(inner: ComPtr<IDxcFile>) -> Self {
-        DxcFile { inner }
+    fn new(inner: ComPtr<IDxcFile>, lib: Arc<Library>) -> Self {
+        Self { inner, lib }
     }
 }
 
@@ -431,6 +442,6 @@ impl Dxc {
             intellisense.as_mut_ptr(),
         )
         .result()?;
-        Ok(DxcIntellisense::new(intellisense))
+        Ok(DxcIntellisense::new(intellisense, self.library_handle()))
     }
 }
@@ -30,6 +30,7 @@
 //!     "copyCs",
 //!     "cs_6_1",
 //!     &vec!["-spirv"],
+//!     None,
 //!     &vec![
 //!         ("MY_DEFINE", Some("Value")),
 //!         ("OTHER_DEFINE", None)
@@ -37,16 +38,43 @@
 //! );
 //! ```
 
+pub(crate) mod com_object;
+pub(crate) mod loader;
 pub(crate) mod unknown;
 
+pub mod cache;
+pub mod cancel;
+pub mod dependencies;
+pub mod diagnostics;
 pub mod fake_sign;
 pub mod ffi;
+pub mod include;
+pub mod options;
 pub mod os;
+pub mod permutation;
+pub mod pool;
+pub mod profile;
+pub mod registry;
+pub mod source_map;
 pub mod utils;
 pub mod wrapper;
 
 pub mod intellisense;
 
+pub use crate::cache::*;
+pub use crate::cancel::*;
+pub use crate::dependencies::*;
+pub use crate::diagnostics::*;
 pub use crate::ffi::*;
-pub use crate::utils::{compile_hlsl, fake_sign_dxil_in_place, validate_dxil, HassleError, Result};
+pub use crate::include::*;
+pub use crate::options::*;
+pub use crate::permutation::*;
+pub use crate::pool::*;
+pub use crate::profile::*;
+pub use crate::registry::*;
+pub use crate::source_map::*;
+pub use crate::utils::{
+    compile_hlsl, compile_hlsl_file, compile_hlsl_strict, compile_hlsl_with_diagnostics,
+    fake_sign_dxil_in_place, preprocess_hlsl, validate_dxil, HassleError, Result,
+};
 pub use crate::wrapper::*;
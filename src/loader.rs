@@ -0,0 +1,161 @@
+use crate::ffi::DxcCreateInstanceProc;
+use crate::utils::{HassleError, Result};
+use libloading::Library;
+use std::path::{Path, PathBuf};
+
+/// Environment variable with the path of the `dxcompiler` library, or the directory containing it.
+pub(crate) const DXC_PATH_ENV: &str = "DXC_PATH";
+
+#[cfg(target_os = "windows")]
+pub(crate) const DXCOMPILER_LIB_NAMES: &[&str] = &["dxcompiler.dll"];
+
+#[cfg(target_os = "macos")]
+pub(crate) const DXCOMPILER_LIB_NAMES: &[&str] =
+    &["libdxcompiler.dylib", "libdxcompiler.3.7.dylib"];
+
+#[cfg(not(any(target_os = "windows", target_os = "macos")))]
+pub(crate) const DXCOMPILER_LIB_NAMES: &[&str] = &["libdxcompiler.so", "libdxcompiler.so.3.7"];
+
+pub(crate) const DXIL_LIB_NAMES: &[&str] = &["dxil.dll"];
+
+/// `path` itself when it is a file, otherwise `path` joined with every library name.
+fn candidates_in(path: &Path, names: &[&str]) -> Vec<PathBuf> {
+    if path.is_file() {
+        vec![path.to_owned()]
+    } else {
+        names.iter().map(|name| path.join(name)).collect()
+    }
+}
+
+/// Paths to try in order when loading a library:
+///
+/// 1. `lib_path`, when given no other location is tried;
+/// 2. the path in the `env_var` environment variable;
+/// 3. the directory of the current executable;
+/// 4. the current working directory;
+/// 5. the bare library names, found through the search paths of the system loader.
+pub(crate) fn library_candidates(
+    lib_path: Option<&Path>,
+    env_var: Option<&str>,
+    names: &[&str],
+) -> Vec<PathBuf> {
+    if let Some(lib_path) = lib_path {
+        return candidates_in(lib_path, names);
+    }
+
+    let mut candidates = vec![];
+
+    if let Some(env_path) = env_var.and_then(std::env::var_os) {
+        candidates.extend(candidates_in(Path::new(&env_path), names));
+    }
+
+    if let Some(exe_dir) = std::env::current_exe()
+        .ok()
+        .as_deref()
+        .and_then(Path::parent)
+    {
+        candidates.extend(candidates_in(exe_dir, names));
+    }
+
+    candidates.extend(candidates_in(Path::new("."), names));
+    candidates.extend(names.iter().map(PathBuf::from));
+
+    let mut unique = vec![];
+    for candidate in candidates {
+        if !unique.contains(&candidate) {
+            unique.push(candidate);
+        }
+    }
+    unique
+}
+
+/// Loads the first of [`library_candidates()`] that exports `DxcCreateInstance`, and
+/// returns it with the path it was loaded from.
+pub(crate) fn load_library(
+    lib_path: Option<&Path>,
+    env_var: Option<&str>,
+    names: &[&str],
+) -> Result<(Library, PathBuf)> {
+    let mut tried = vec![];
+
+    for candidate in library_candidates(lib_path, env_var, names) {
+        let library = match unsafe { Library::new(&candidate) } {
+            Ok(library) => library,
+            Err(e) => {
+                tried.push((candidate, e));
+                continue;
+            }
+        };
+
+        let create_instance =
+            unsafe { library.get::<DxcCreateInstanceProc>(b"DxcCreateInstance\0") }.map(|_| ());
+        match create_instance {
+            Ok(()) => return Ok((library, candidate)),
+            Err(e) => tried.push((candidate, e)),
+        }
+    }
+
+    Err(HassleError::LoadLibraryError { tried })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    const NAMES: &[&str] = &["libtest.so", "libtest.so.1"];
+
+    fn in_dir(dir: &Path) -> Vec<PathBuf> {
+        NAMES.iter().map(|name| dir.join(name)).collect()
+    }
+
+    #[test]
+    fn search_order() {
+        let dir = std::env::temp_dir().join(format!("hassle-loader-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("custom.so");
+        fs::write(&file, "").unwrap();
+
+        let exe_dir = std::env::current_exe()
+            .unwrap()
+            .parent()
+            .unwrap()
+            .to_owned();
+        let mut fallback = in_dir(&exe_dir);
+        fallback.extend(in_dir(Path::new(".")));
+        fallback.extend(NAMES.iter().map(PathBuf::from));
+
+        // Not `DXC_PATH`, which other tests running in parallel may read
+        let env_var = "HASSLE_LOADER_TEST_SEARCH_ORDER";
+        std::env::remove_var(env_var);
+        assert_eq!(library_candidates(None, Some(env_var), NAMES), fallback);
+        assert_eq!(library_candidates(None, None, NAMES), fallback);
+
+        std::env::set_var(env_var, &file);
+        let mut expected = vec![file.clone()];
+        expected.extend(fallback.iter().cloned());
+        assert_eq!(library_candidates(None, Some(env_var), NAMES), expected);
+
+        std::env::set_var(env_var, &dir);
+        let mut expected = in_dir(&dir);
+        expected.extend(fallback.iter().cloned());
+        assert_eq!(library_candidates(None, Some(env_var), NAMES), expected);
+
+        // An explicit path is the only location tried
+        assert_eq!(
+            library_candidates(Some(&file), Some(env_var), NAMES),
+            [file.clone()]
+        );
+        assert_eq!(
+            library_candidates(Some(&dir), Some(env_var), NAMES),
+            in_dir(&dir)
+        );
+
+        // The executable directory is not tried twice
+        std::env::set_var(env_var, &exe_dir);
+        assert_eq!(library_candidates(None, Some(env_var), NAMES), fallback);
+
+        std::env::remove_var(env_var);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,298 @@
+/// Optimization level passed to the compiler, defaults to [`OptimizationLevel::O3`] in DXC.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum OptimizationLevel {
+    /// `-Od`, disables all optimizations.
+    Disabled,
+    /// `-O0`
+    O0,
+    /// `-O1`
+    O1,
+    /// `-O2`
+    O2,
+    /// `-O3`
+    O3,
+}
+
+impl OptimizationLevel {
+    fn as_arg(self) -> &'static str {
+        match self {
+            Self::Disabled => "-Od",
+            Self::O0 => "-O0",
+            Self::O1 => "-O1",
+            Self::O2 => "-O2",
+            Self::O3 => "-O3",
+        }
+    }
+}
+
+/// HLSL language version, passed as `-HV <version>`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum HlslVersion {
+    V2016,
+    V2017,
+    V2018,
+    V2021,
+}
+
+impl HlslVersion {
+    fn as_arg(self) -> &'static str {
+        match self {
+            Self::V2016 => "2016",
+            Self::V2017 => "2017",
+            Self::V2018 => "2018",
+            Self::V2021 => "2021",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum MatrixPacking {
+    /// `-Zpr`
+    RowMajor,
+    /// `-Zpc`
+    ColumnMajor,
+}
+
+impl MatrixPacking {
+    fn as_arg(self) -> &'static str {
+        match self {
+            Self::RowMajor => "-Zpr",
+            Self::ColumnMajor => "-Zpc",
+        }
+    }
+}
+
+/// SPIR-V target environment, passed as `-fspv-target-env=<env>`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum SpirvTargetEnv {
+    Vulkan1_0,
+    Vulkan1_1,
+    Vulkan1_1Spirv1_4,
+    Vulkan1_2,
+    Vulkan1_3,
+    Universal1_5,
+}
+
+impl SpirvTargetEnv {
+    fn as_arg(self) -> &'static str {
+        match self {
+            Self::Vulkan1_0 => "-fspv-target-env=vulkan1.0",
+            Self::Vulkan1_1 => "-fspv-target-env=vulkan1.1",
+            Self::Vulkan1_1Spirv1_4 => "-fspv-target-env=vulkan1.1spirv1.4",
+            Self::Vulkan1_2 => "-fspv-target-env=vulkan1.2",
+            Self::Vulkan1_3 => "-fspv-target-env=vulkan1.3",
+            Self::Universal1_5 => "-fspv-target-env=universal1.5",
+        }
+    }
+}
+
+/// HLSL register type that a SPIR-V binding shift applies to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum BindingShiftKind {
+    /// `b` registers, `-fvk-b-shift`
+    ConstantBuffer,
+    /// `t` registers, `-fvk-t-shift`
+    Texture,
+    /// `s` registers, `-fvk-s-shift`
+    Sampler,
+    /// `u` registers, `-fvk-u-shift`
+    UnorderedAccess,
+}
+
+impl BindingShiftKind {
+    fn as_arg(self) -> &'static str {
+        match self {
+            Self::ConstantBuffer => "-fvk-b-shift",
+            Self::Texture => "-fvk-t-shift",
+            Self::Sampler => "-fvk-s-shift",
+            Self::UnorderedAccess => "-fvk-u-shift",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct BindingShift {
+    pub kind: BindingShiftKind,
+    pub shift: u32,
+    pub space: u32,
+}
+
+/// Typed builder for the compiler arguments that are otherwise passed as raw `args: &[&str]`.
+///
+/// Flags that are not covered by the builder can still be passed through [`CompileOptions::arg()`].
+///
+/// ```
+/// use hassle_rs::{CompileOptions, OptimizationLevel, SpirvTargetEnv};
+///
+/// let options = CompileOptions::new()
+///     .optimization_level(OptimizationLevel::O3)
+///     .spirv_target_env(SpirvTargetEnv::Vulkan1_2)
+///     .arg("-fspv-reflect");
+///
+/// let args = options.to_args();
+/// assert_eq!(args, ["-O3", "-spirv", "-fspv-target-env=vulkan1.2", "-fspv-reflect"]);
+///
+/// // Borrow as the `&[&str]` taken by `compile_hlsl` and `DxcCompiler::compile`:
+/// let args = args.iter().map(String::as_str).collect::<Vec<_>>();
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct CompileOptions {
+    optimization_level: Option<OptimizationLevel>,
+    hlsl_version: Option<HlslVersion>,
+    matrix_packing: Option<MatrixPacking>,
+    enable_16bit_types: bool,
+    warnings_as_errors: bool,
+    debug_info: bool,
+    strip_debug: bool,
+    strip_reflection: bool,
+    strip_root_signature: bool,
+    spirv: bool,
+    spirv_target_env: Option<SpirvTargetEnv>,
+    binding_shifts: Vec<BindingShift>,
+    extra_args: Vec<String>,
+}
+
+impl CompileOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn optimization_level(mut self, level: OptimizationLevel) -> Self {
+        self.optimization_level = Some(level);
+        self
+    }
+
+    pub fn hlsl_version(mut self, version: HlslVersion) -> Self {
+        self.hlsl_version = Some(version);
+        self
+    }
+
+    pub fn matrix_packing(mut self, packing: MatrixPacking) -> Self {
+        self.matrix_packing = Some(packing);
+        self
+    }
+
+    /// `-enable-16bit-types`, requires shader model 6.2 or higher.
+    pub fn enable_16bit_types(mut self, enable: bool) -> Self {
+        self.enable_16bit_types = enable;
+        self
+    }
+
+    /// `-WX`
+    pub fn warnings_as_errors(mut self, enable: bool) -> Self {
+        self.warnings_as_errors = enable;
+        self
+    }
+
+    /// `-Zi`
+    pub fn debug_info(mut self, enable: bool) -> Self {
+        self.debug_info = enable;
+        self
+    }
+
+    /// `-Qstrip_debug`
+    pub fn strip_debug(mut self, enable: bool) -> Self {
+        self.strip_debug = enable;
+        self
+    }
+
+    /// `-Qstrip_reflect`
+    pub fn strip_reflection(mut self, enable: bool) -> Self {
+        self.strip_reflection = enable;
+        self
+    }
+
+    /// `-Qstrip_rootsignature`
+    pub fn strip_root_signature(mut self, enable: bool) -> Self {
+        self.strip_root_signature = enable;
+        self
+    }
+
+    /// `-spirv`
+    pub fn spirv(mut self, enable: bool) -> Self {
+        self.spirv = enable;
+        self
+    }
+
+    /// Sets the SPIR-V target environment, implies [`CompileOptions::spirv()`].
+    pub fn spirv_target_env(mut self, env: SpirvTargetEnv) -> Self {
+        self.spirv_target_env = Some(env);
+        self
+    }
+
+    /// Shifts all `kind` registers in `space` by `shift` when assigning SPIR-V bindings,
+    /// implies [`CompileOptions::spirv()`].
+    pub fn binding_shift(mut self, kind: BindingShiftKind, shift: u32, space: u32) -> Self {
+        self.binding_shifts
+            .push(BindingShift { kind, shift, space });
+        self
+    }
+
+    /// Appends a raw argument that is passed to the compiler verbatim.
+    pub fn arg(mut self, arg: impl Into<String>) -> Self {
+        self.extra_args.push(arg.into());
+        self
+    }
+
+    /// Appends raw arguments that are passed to the compiler verbatim.
+    pub fn args<I>(mut self, args: I) -> Self
+    where
+        I: IntoIterator,
+        I::Item: Into<String>,
+    {
+        self.extra_args.extend(args.into_iter().map(Into::into));
+        self
+    }
+
+    /// Lowers the options to the argument list consumed by the compiler.
+    pub fn to_args(&self) -> Vec<String> {
+        let mut args: Vec<String> = vec![];
+
+        if let Some(level) = self.optimization_level {
+            args.push(level.as_arg().to_owned());
+        }
+
+        if let Some(version) = self.hlsl_version {
+            args.push("-HV".to_owned());
+            args.push(version.as_arg().to_owned());
+        }
+
+        if let Some(packing) = self.matrix_packing {
+            args.push(packing.as_arg().to_owned());
+        }
+
+        let flags = [
+            (self.enable_16bit_types, "-enable-16bit-types"),
+            (self.warnings_as_errors, "-WX"),
+            (self.debug_info, "-Zi"),
+            (self.strip_debug, "-Qstrip_debug"),
+            (self.strip_reflection, "-Qstrip_reflect"),
+            (self.strip_root_signature, "-Qstrip_rootsignature"),
+        ];
+
+        args.extend(
+            flags
+                .iter()
+                .filter(|(enabled, _)| *enabled)
+                .map(|(_, flag)| flag.to_string()),
+        );
+
+        if self.spirv || self.spirv_target_env.is_some() || !self.binding_shifts.is_empty() {
+            args.push("-spirv".to_owned());
+        }
+
+        if let Some(env) = self.spirv_target_env {
+            args.push(env.as_arg().to_owned());
+        }
+
+        for binding_shift in &self.binding_shifts {
+            args.push(binding_shift.kind.as_arg().to_owned());
+            args.push(binding_shift.shift.to_string());
+            args.push(binding_shift.space.to_string());
+        }
+
+        args.extend(self.extra_args.iter().cloned());
+
+        args
+    }
+}
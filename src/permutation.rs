@@ -0,0 +1,362 @@
+use crate::pool::{CompileJob, CompilerPool};
+use crate::utils::{compile_hlsl_with_compiler, HassleError, Result};
+use crate::wrapper::{Dxc, DxcIdentity, DxcIncludeHandler2};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+
+/// One combination of define values produced by [`PermutationSet::expand()`].
+///
+/// Holds a value for every axis in declaration order, where `None` means the define
+/// is not set at all.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct PermutationKey {
+    values: Vec<(String, Option<String>)>,
+}
+
+impl PermutationKey {
+    /// Value of the define `name`, `None` if it is not set in this permutation.
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.values
+            .iter()
+            .find(|(axis, _)| axis == name)
+            .and_then(|(_, value)| value.as_deref())
+    }
+
+    pub fn is_defined(&self, name: &str) -> bool {
+        self.get(name).is_some()
+    }
+
+    /// The defines of this permutation, in the form taken by [`crate::compile_hlsl()`].
+    pub fn defines(&self) -> Vec<(&str, Option<&str>)> {
+        self.values
+            .iter()
+            .filter_map(|(name, value)| Some((name.as_str(), Some(value.as_deref()?))))
+            .collect()
+    }
+}
+
+impl fmt::Display for PermutationKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let defines = self.defines();
+        if defines.is_empty() {
+            return f.write_str("no defines");
+        }
+        for (i, (name, value)) in defines.iter().enumerate() {
+            if i > 0 {
+                f.write_str(" ")?;
+            }
+            write!(f, "{}={}", name, value.unwrap())?;
+        }
+        Ok(())
+    }
+}
+
+type ExclusionRule = Box<dyn Fn(&PermutationKey) -> bool + Send + Sync>;
+
+/// Describes the define combinations a shader is compiled with.
+///
+/// Every axis is a define with a list of values, the permutations are the cartesian
+/// product of all axes minus the combinations rejected by [`PermutationSet::exclude()`].
+///
+/// ```
+/// use hassle_rs::PermutationSet;
+///
+/// let permutations = PermutationSet::new()
+///     .toggle("USE_SHADOWS")
+///     .values("QUALITY", &["LOW", "HIGH"])
+///     // Shadows are always disabled on low quality
+///     .exclude(|key| key.is_defined("USE_SHADOWS") && key.get("QUALITY") == Some("LOW"));
+///
+/// let keys = permutations.expand();
+/// assert_eq!(keys.len(), 3);
+/// assert_eq!(keys[2].to_string(), "USE_SHADOWS=1 QUALITY=HIGH");
+/// assert_eq!(keys[2].defines(), [("USE_SHADOWS", Some("1")), ("QUALITY", Some("HIGH"))]);
+/// ```
+#[derive(Default)]
+pub struct PermutationSet {
+    axes: Vec<(String, Vec<Option<String>>)>,
+    exclusions: Vec<ExclusionRule>,
+}
+
+impl fmt::Debug for PermutationSet {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PermutationSet")
+            .field("axes", &self.axes)
+            .field("exclusions", &self.exclusions.len())
+            .finish()
+    }
+}
+
+impl PermutationSet {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds an axis where `name` is either not defined or defined as `1`.
+    pub fn toggle(mut self, name: impl Into<String>) -> Self {
+        self.axes
+            .push((name.into(), vec![None, Some("1".to_owned())]));
+        self
+    }
+
+    /// Adds an axis where `name` is defined as each of `values`.
+    pub fn values<I>(mut self, name: impl Into<String>, values: I) -> Self
+    where
+        I: IntoIterator,
+        I::Item: AsRef<str>,
+    {
+        let values = values
+            .into_iter()
+            .map(|value| Some(value.as_ref().to_owned()))
+            .collect();
+        self.axes.push((name.into(), values));
+        self
+    }
+
+    /// Skips all permutations for which `rule` returns `true`.
+    pub fn exclude(
+        mut self,
+        rule: impl Fn(&PermutationKey) -> bool + Send + Sync + 'static,
+    ) -> Self {
+        self.exclusions.push(Box::new(rule));
+        self
+    }
+
+    /// All permutations that are not excluded, with the last axis changing fastest.
+    pub fn expand(&self) -> Vec<PermutationKey> {
+        let mut keys = vec![PermutationKey { values: vec![] }];
+
+        for (name, values) in &self.axes {
+            keys = keys
+                .iter()
+                .flat_map(|key| {
+                    values.iter().map(move |value| {
+                        let mut key = key.clone();
+                        key.values.push((name.clone(), value.clone()));
+                        key
+                    })
+                })
+                .collect();
+        }
+
+        keys.retain(|key| !self.exclusions.iter().any(|rule| rule(key)));
+        keys
+    }
+
+    /// Compiles every permutation of the shader, with the same arguments as
+    /// [`crate::compile_hlsl()`]. `defines` are passed to every permutation, before
+    /// the defines of the permutation itself.
+    ///
+    /// Stops at the first permutation that fails to compile, and returns a
+    /// [`HassleError::PermutationError`] with its key.
+    pub fn compile(
+        &self,
+        source_name: &str,
+        shader_text: &str,
+        entry_point: &str,
+        target_profile: impl Into<String>,
+        args: &[&str],
+        mut include_handler: Option<&mut dyn DxcIncludeHandler2>,
+        defines: &[(&str, Option<&str>)],
+    ) -> Result<PermutationTable> {
+        let dxc = Dxc::new(None)?;
+
+        let compiler = dxc.create_compiler()?;
+        let library = dxc.create_library()?;
+
+        let blob = library.create_blob_with_encoding_from_str(shader_text)?;
+        let target_profile = target_profile.into();
+        let mut table = PermutationTable {
+            compiler: Some(compiler.identity().clone()),
+            ..Default::default()
+        };
+
+        for key in self.expand() {
+            let mut permutation_defines = defines.to_vec();
+            permutation_defines.extend(key.defines());
+
+            let result = compile_hlsl_with_compiler(
+                &compiler,
+                &library,
+                &blob,
+                source_name,
+                entry_point,
+                target_profile.clone(),
+                args,
+                include_handler
+                    .as_mut()
+                    .map(|h| &mut **h as &mut dyn DxcIncludeHandler2),
+                &permutation_defines,
+                false,
+            );
+
+            match result {
+                Ok((data, _)) => table.insert(key, data),
+                Err(e) => return Err(permutation_error(&key, e)),
+            }
+        }
+
+        Ok(table)
+    }
+
+    /// Compiles every permutation of `job` in parallel on `pool`, adding the defines
+    /// of each permutation after those of `job`.
+    ///
+    /// Includes are loaded through a handler created by `make_include_handler` for
+    /// every permutation, as in [`CompilerPool::compile_with_include_handler()`].
+    pub fn compile_with_pool<F, H>(
+        &self,
+        pool: &mut CompilerPool<'_>,
+        job: &CompileJob,
+        make_include_handler: F,
+    ) -> Result<PermutationTable>
+    where
+        F: Fn() -> H + Sync,
+        H: DxcIncludeHandler2,
+    {
+        let keys = self.expand();
+        let jobs = keys
+            .iter()
+            .map(|key| {
+                key.defines()
+                    .into_iter()
+                    .fold(job.clone(), |job, (name, value)| job.define(name, value))
+            })
+            .collect::<Vec<_>>();
+
+        let mut table = PermutationTable {
+            compiler: Some(pool.dxc().identity().clone()),
+            ..Default::default()
+        };
+        for (key, result) in keys
+            .into_iter()
+            .zip(pool.compile_with_include_handler(&jobs, make_include_handler))
+        {
+            match result {
+                Ok((data, _)) => table.insert(key, data),
+                Err(e) => return Err(permutation_error(&key, e)),
+            }
+        }
+
+        Ok(table)
+    }
+}
+
+fn permutation_error(key: &PermutationKey, inner: HassleError) -> HassleError {
+    HassleError::PermutationError {
+        permutation: key.to_string(),
+        inner: Box::new(inner),
+    }
+}
+
+/// Compiled permutations, where permutations with byte-identical output share a
+/// single blob.
+#[derive(Clone, Debug, Default)]
+pub struct PermutationTable {
+    blobs: Vec<Vec<u8>>,
+    entries: Vec<(PermutationKey, usize)>,
+    /// Indices into `blobs`, keyed by the hash of the blob.
+    blob_indices: HashMap<u64, Vec<usize>>,
+    compiler: Option<DxcIdentity>,
+}
+
+impl PermutationTable {
+    fn blob_hash(data: &[u8]) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        data.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    fn insert(&mut self, key: PermutationKey, data: Vec<u8>) {
+        let indices = self.blob_indices.entry(Self::blob_hash(&data)).or_default();
+        let blobs = &mut self.blobs;
+        let index = match indices.iter().find(|&&index| blobs[index] == data) {
+            Some(&index) => index,
+            None => {
+                blobs.push(data);
+                indices.push(blobs.len() - 1);
+                blobs.len() - 1
+            }
+        };
+        self.entries.push((key, index));
+    }
+
+    pub fn get(&self, key: &PermutationKey) -> Option<&[u8]> {
+        self.blob_index(key)
+            .map(|index| self.blobs[index].as_slice())
+    }
+
+    /// Index of the blob of `key` in [`PermutationTable::unique_blobs()`].
+    pub fn blob_index(&self, key: &PermutationKey) -> Option<usize> {
+        self.entries
+            .iter()
+            .find(|(entry, _)| entry == key)
+            .map(|(_, index)| *index)
+    }
+
+    /// The library that compiled the permutations.
+    pub fn compiler(&self) -> Option<&DxcIdentity> {
+        self.compiler.as_ref()
+    }
+
+    /// Every distinct compiled blob, referenced by one or more permutations.
+    pub fn unique_blobs(&self) -> &[Vec<u8>] {
+        &self.blobs
+    }
+
+    /// All permutations in the order they were expanded, with their blob.
+    pub fn iter(&self) -> impl Iterator<Item = (&PermutationKey, &[u8])> {
+        self.entries
+            .iter()
+            .map(move |(key, index)| (key, self.blobs[*index].as_slice()))
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn identical_blobs_are_shared() {
+        let keys = PermutationSet::new()
+            .values("QUALITY", &["LOW", "MEDIUM", "HIGH", "ULTRA"])
+            .expand();
+        let mut table = PermutationTable::default();
+
+        // Make `ultra` collide with the first blob, it must still get a blob of its own
+        table
+            .blob_indices
+            .insert(PermutationTable::blob_hash(b"ultra"), vec![0]);
+
+        table.insert(keys[0].clone(), b"shared".to_vec());
+        table.insert(keys[1].clone(), b"high".to_vec());
+        table.insert(keys[2].clone(), b"shared".to_vec());
+        table.insert(keys[3].clone(), b"ultra".to_vec());
+
+        assert_eq!(table.len(), 4);
+        assert_eq!(
+            table.unique_blobs(),
+            [b"shared".to_vec(), b"high".to_vec(), b"ultra".to_vec()]
+        );
+        assert_eq!(table.blob_index(&keys[0]), Some(0));
+        assert_eq!(table.blob_index(&keys[1]), Some(1));
+        assert_eq!(table.blob_index(&keys[2]), Some(0));
+        assert_eq!(table.blob_index(&keys[3]), Some(2));
+        assert_eq!(table.get(&keys[2]), Some(&b"shared"[..]));
+        assert_eq!(table.get(&keys[3]), Some(&b"ultra"[..]));
+
+        let other = PermutationSet::new().toggle("OTHER").expand();
+        assert_eq!(table.get(&other[1]), None);
+        assert_eq!(table.blob_index(&other[1]), None);
+    }
+}
@@ -0,0 +1,195 @@
+use crate::diagnostics::Diagnostic;
+use crate::include::FileIncludeHandler;
+use crate::utils::{compile_hlsl_with_compiler, Result};
+use crate::wrapper::{Dxc, DxcCompiler, DxcIncludeHandler2, DxcLibrary};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// A single compilation for [`CompilerPool::compile()`], with the same inputs as
+/// [`crate::compile_hlsl()`].
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct CompileJob {
+    pub source_name: String,
+    pub source: String,
+    pub entry_point: String,
+    pub target_profile: String,
+    pub args: Vec<String>,
+    pub defines: Vec<(String, Option<String>)>,
+}
+
+impl CompileJob {
+    pub fn new(
+        source_name: impl Into<String>,
+        source: impl Into<String>,
+        entry_point: impl Into<String>,
+        target_profile: impl Into<String>,
+    ) -> Self {
+        Self {
+            source_name: source_name.into(),
+            source: source.into(),
+            entry_point: entry_point.into(),
+            target_profile: target_profile.into(),
+            args: vec![],
+            defines: vec![],
+        }
+    }
+
+    pub fn arg(mut self, arg: impl Into<String>) -> Self {
+        self.args.push(arg.into());
+        self
+    }
+
+    pub fn args<I>(mut self, args: I) -> Self
+    where
+        I: IntoIterator,
+        I::Item: Into<String>,
+    {
+        self.args.extend(args.into_iter().map(Into::into));
+        self
+    }
+
+    pub fn define(mut self, name: impl Into<String>, value: Option<&str>) -> Self {
+        self.defines.push((name.into(), value.map(str::to_owned)));
+        self
+    }
+
+    pub(crate) fn compile(
+        &self,
+        compiler: &DxcCompiler,
+        library: &DxcLibrary,
+        include_handler: Option<&mut dyn DxcIncludeHandler2>,
+    ) -> Result<(Vec<u8>, Vec<Diagnostic>)> {
+        let args = self.args.iter().map(String::as_str).collect::<Vec<_>>();
+        let defines = self
+            .defines
+            .iter()
+            .map(|(name, value)| (name.as_str(), value.as_deref()))
+            .collect::<Vec<_>>();
+
+        let blob = library.create_blob_with_encoding_from_str(&self.source)?;
+
+        compile_hlsl_with_compiler(
+            compiler,
+            library,
+            &blob,
+            &self.source_name,
+            &self.entry_point,
+            self.target_profile.clone(),
+            &args,
+            include_handler,
+            &defines,
+            false,
+        )
+    }
+}
+
+/// Compiles batches of shaders in parallel, with one [`DxcCompiler`] per worker thread.
+///
+/// DXC compilers must not be used from multiple threads at once, so every worker
+/// owns its own compiler, all created from the same [`Dxc`].
+///
+/// ```no_run
+/// use hassle_rs::{CompileJob, CompilerPool, Dxc};
+///
+/// let dxc = Dxc::new(None).unwrap();
+/// let mut pool = CompilerPool::new(&dxc, 4).unwrap();
+///
+/// let source = "[numthreads(64, 1, 1)] void main() {}";
+/// let jobs = ["cs_6_0", "cs_6_5"]
+///     .iter()
+///     .map(|profile| CompileJob::new("main.hlsl", source, "main", *profile))
+///     .collect::<Vec<_>>();
+///
+/// // Results are in the same order as the jobs
+/// for (job, result) in jobs.iter().zip(pool.compile(&jobs)) {
+///     println!("{}: {:?}", job.target_profile, result.map(|(dxil, _)| dxil.len()));
+/// }
+/// ```
+#[derive(Debug)]
+pub struct CompilerPool<'d> {
+    dxc: &'d Dxc,
+    workers: Vec<(DxcCompiler, DxcLibrary)>,
+}
+
+impl<'d> CompilerPool<'d> {
+    /// Creates a pool with `num_workers` compilers, at least one.
+    pub fn new(dxc: &'d Dxc, num_workers: usize) -> Result<Self> {
+        let workers = (0..num_workers.max(1))
+            .map(|_| Ok((dxc.create_compiler()?, dxc.create_library()?)))
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(Self { dxc, workers })
+    }
+
+    /// Creates a pool with a compiler for every thread the system can run in parallel.
+    pub fn with_available_parallelism(dxc: &'d Dxc) -> Result<Self> {
+        let num_workers = std::thread::available_parallelism().map_or(1, |n| n.get());
+        Self::new(dxc, num_workers)
+    }
+
+    pub fn dxc(&self) -> &'d Dxc {
+        self.dxc
+    }
+
+    pub fn num_workers(&self) -> usize {
+        self.workers.len()
+    }
+
+    /// Compiles all `jobs` in parallel and returns their results in the same order,
+    /// loading includes relative to the current working directory.
+    pub fn compile(&mut self, jobs: &[CompileJob]) -> Vec<Result<(Vec<u8>, Vec<Diagnostic>)>> {
+        self.compile_with_include_handler(jobs, FileIncludeHandler::new)
+    }
+
+    /// Same as [`CompilerPool::compile()`], but loads includes through a handler
+    /// created by `make_include_handler` for every job.
+    pub fn compile_with_include_handler<F, H>(
+        &mut self,
+        jobs: &[CompileJob],
+        make_include_handler: F,
+    ) -> Vec<Result<(Vec<u8>, Vec<Diagnostic>)>>
+    where
+        F: Fn() -> H + Sync,
+        H: DxcIncludeHandler2,
+    {
+        let next_job = AtomicUsize::new(0);
+
+        let finished = std::thread::scope(|scope| {
+            let threads = self
+                .workers
+                .iter_mut()
+                .take(jobs.len())
+                .map(|(compiler, library)| {
+                    let next_job = &next_job;
+                    let make_include_handler = &make_include_handler;
+                    scope.spawn(move || {
+                        let mut finished = vec![];
+                        loop {
+                            let index = next_job.fetch_add(1, Ordering::Relaxed);
+                            let job = match jobs.get(index) {
+                                Some(job) => job,
+                                None => break,
+                            };
+                            let mut include_handler = make_include_handler();
+                            finished.push((
+                                index,
+                                job.compile(compiler, library, Some(&mut include_handler)),
+                            ));
+                        }
+                        finished
+                    })
+                })
+                .collect::<Vec<_>>();
+
+            threads
+                .into_iter()
+                .flat_map(|thread| thread.join().unwrap())
+                .collect::<Vec<_>>()
+        });
+
+        let mut results = (0..jobs.len()).map(|_| None).collect::<Vec<_>>();
+        for (index, result) in finished {
+            results[index] = Some(result);
+        }
+        results.into_iter().map(Option::unwrap).collect()
+    }
+}
@@ -0,0 +1,281 @@
+use crate::utils::HassleError;
+use std::fmt;
+use std::str::FromStr;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum ShaderStage {
+    Vertex,
+    Pixel,
+    Geometry,
+    Hull,
+    Domain,
+    Compute,
+    Mesh,
+    Amplification,
+    Library,
+}
+
+impl ShaderStage {
+    pub const ALL: [ShaderStage; 9] = [
+        Self::Vertex,
+        Self::Pixel,
+        Self::Geometry,
+        Self::Hull,
+        Self::Domain,
+        Self::Compute,
+        Self::Mesh,
+        Self::Amplification,
+        Self::Library,
+    ];
+
+    /// Prefix of the stage in a target profile string, such as `cs` in `cs_6_1`.
+    pub fn prefix(self) -> &'static str {
+        match self {
+            Self::Vertex => "vs",
+            Self::Pixel => "ps",
+            Self::Geometry => "gs",
+            Self::Hull => "hs",
+            Self::Domain => "ds",
+            Self::Compute => "cs",
+            Self::Mesh => "ms",
+            Self::Amplification => "as",
+            Self::Library => "lib",
+        }
+    }
+
+    /// Lowest shader model that can target this stage.
+    pub fn minimum_shader_model(self) -> ShaderModel {
+        match self {
+            Self::Mesh | Self::Amplification => ShaderModel::V6_5,
+            Self::Library => ShaderModel::V6_1,
+            _ => ShaderModel::V6_0,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum ShaderModel {
+    V6_0,
+    V6_1,
+    V6_2,
+    V6_3,
+    V6_4,
+    V6_5,
+    V6_6,
+    V6_7,
+    V6_8,
+    /// `6_x`, only for offline libraries that are linked into a shader of a concrete
+    /// shader model later.
+    Offline,
+}
+
+impl ShaderModel {
+    /// Every concrete shader model, without [`ShaderModel::Offline`].
+    pub const ALL: [ShaderModel; 9] = [
+        Self::V6_0,
+        Self::V6_1,
+        Self::V6_2,
+        Self::V6_3,
+        Self::V6_4,
+        Self::V6_5,
+        Self::V6_6,
+        Self::V6_7,
+        Self::V6_8,
+    ];
+
+    pub fn major(self) -> u32 {
+        6
+    }
+
+    /// `None` for [`ShaderModel::Offline`].
+    pub fn minor(self) -> Option<u32> {
+        match self {
+            Self::Offline => None,
+            _ => Some(self as u32),
+        }
+    }
+
+    pub fn from_version(major: u32, minor: u32) -> Option<Self> {
+        if major != 6 {
+            return None;
+        }
+        Self::ALL.get(minor as usize).copied()
+    }
+
+    pub fn supports(self, stage: ShaderStage) -> bool {
+        match self {
+            Self::Offline => stage == ShaderStage::Library,
+            _ => self >= stage.minimum_shader_model(),
+        }
+    }
+
+    /// All stages that can be targeted with this shader model.
+    pub fn supported_stages(self) -> Vec<ShaderStage> {
+        ShaderStage::ALL
+            .iter()
+            .copied()
+            .filter(|&stage| self.supports(stage))
+            .collect()
+    }
+}
+
+impl fmt::Display for ShaderModel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.minor() {
+            Some(minor) => write!(f, "{}_{}", self.major(), minor),
+            None => write!(f, "{}_x", self.major()),
+        }
+    }
+}
+
+/// Strongly typed target profile, such as `cs_6_1`.
+///
+/// All compile entry points accept a [`ShaderProfile`] as well as a plain string:
+///
+/// ```
+/// use hassle_rs::{ShaderModel, ShaderProfile, ShaderStage};
+///
+/// let profile = ShaderProfile::new(ShaderStage::Compute, ShaderModel::V6_1).unwrap();
+/// assert_eq!(profile.to_string(), "cs_6_1");
+/// assert_eq!("cs_6_1".parse::<ShaderProfile>().unwrap(), profile);
+///
+/// // Mesh shaders require shader model 6.5
+/// assert!(ShaderProfile::new(ShaderStage::Mesh, ShaderModel::V6_4).is_err());
+///
+/// // Offline libraries are linked into a shader of a concrete shader model later
+/// assert_eq!("lib_6_x".parse::<ShaderProfile>().unwrap().model(), ShaderModel::Offline);
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ShaderProfile {
+    stage: ShaderStage,
+    model: ShaderModel,
+}
+
+impl ShaderProfile {
+    pub fn new(stage: ShaderStage, model: ShaderModel) -> Result<Self, HassleError> {
+        if model.supports(stage) {
+            Ok(Self { stage, model })
+        } else {
+            Err(HassleError::InvalidTargetProfile(format!(
+                "{}_{}",
+                stage.prefix(),
+                model
+            )))
+        }
+    }
+
+    pub fn stage(&self) -> ShaderStage {
+        self.stage
+    }
+
+    pub fn model(&self) -> ShaderModel {
+        self.model
+    }
+}
+
+impl fmt::Display for ShaderProfile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}_{}", self.stage.prefix(), self.model)
+    }
+}
+
+impl FromStr for ShaderProfile {
+    type Err = HassleError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || HassleError::InvalidTargetProfile(s.to_owned());
+
+        let mut parts = s.split('_');
+        let (prefix, major, minor) = match (parts.next(), parts.next(), parts.next(), parts.next())
+        {
+            (Some(prefix), Some(major), Some(minor), None) => (prefix, major, minor),
+            _ => return Err(invalid()),
+        };
+
+        let stage = ShaderStage::ALL
+            .iter()
+            .copied()
+            .find(|stage| stage.prefix() == prefix)
+            .ok_or_else(invalid)?;
+
+        let model = match (major, minor) {
+            ("6", "x") => ShaderModel::Offline,
+            _ => {
+                let major = major.parse().map_err(|_| invalid())?;
+                let minor = minor.parse().map_err(|_| invalid())?;
+                ShaderModel::from_version(major, minor).ok_or_else(invalid)?
+            }
+        };
+
+        Self::new(stage, model)
+    }
+}
+
+impl From<ShaderProfile> for String {
+    fn from(profile: ShaderProfile) -> Self {
+        profile.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_and_display() {
+        for &stage in ShaderStage::ALL.iter() {
+            for &model in ShaderModel::ALL.iter() {
+                let text = format!("{}_6_{}", stage.prefix(), model.minor().unwrap());
+                match ShaderProfile::new(stage, model) {
+                    Ok(profile) => {
+                        assert_eq!(profile.to_string(), text);
+                        assert_eq!(text.parse::<ShaderProfile>().unwrap(), profile);
+                    }
+                    Err(_) => assert!(text.parse::<ShaderProfile>().is_err(), "{}", text),
+                }
+            }
+        }
+
+        let offline = "lib_6_x".parse::<ShaderProfile>().unwrap();
+        assert_eq!(offline.stage(), ShaderStage::Library);
+        assert_eq!(offline.model(), ShaderModel::Offline);
+        assert_eq!(offline.to_string(), "lib_6_x");
+
+        for invalid in [
+            "", "cs", "cs_6", "cs_6_", "cs_6_9", "cs_7_0", "cs_6_0_1", "xs_6_0", "cs_6_x",
+            "lib_5_x", "CS_6_0",
+        ]
+        .iter()
+        {
+            assert!(invalid.parse::<ShaderProfile>().is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn supports() {
+        use ShaderModel::*;
+        use ShaderStage::*;
+
+        let table = [
+            (V6_0, &[Vertex, Pixel, Geometry, Hull, Domain, Compute][..]),
+            (
+                V6_1,
+                &[Vertex, Pixel, Geometry, Hull, Domain, Compute, Library],
+            ),
+            (
+                V6_4,
+                &[Vertex, Pixel, Geometry, Hull, Domain, Compute, Library],
+            ),
+            (V6_5, &ShaderStage::ALL),
+            (V6_8, &ShaderStage::ALL),
+            (Offline, &[Library]),
+        ];
+        for (model, stages) in table.iter() {
+            assert_eq!(model.supported_stages(), *stages, "{}", model);
+        }
+
+        assert!(ShaderProfile::new(Library, V6_1).is_ok());
+        assert!(ShaderProfile::new(Library, V6_0).is_err());
+        assert!(ShaderProfile::new(Compute, Offline).is_err());
+    }
+}
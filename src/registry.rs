@@ -0,0 +1,88 @@
+use crate::utils::Result;
+use crate::wrapper::{Dxc, DxcIdentity};
+use std::path::PathBuf;
+
+/// A set of `dxcompiler` libraries loaded side by side, for example to compile older
+/// content with the compiler it was authored against while new content uses a newer
+/// release.
+///
+/// Libraries are looked up by a tag chosen when loading them, or by the version they
+/// report.
+///
+/// ```no_run
+/// use hassle_rs::DxcRegistry;
+///
+/// let mut registry = DxcRegistry::new();
+/// registry
+///     .load(Some("dxc-1.6/libdxcompiler.so".into()), "legacy")
+///     .unwrap();
+/// registry
+///     .load(Some("dxc-1.8/libdxcompiler.so".into()), "latest")
+///     .unwrap();
+///
+/// let legacy = registry.by_tag("legacy").unwrap();
+/// let newest = registry.with_min_version(1, 7).unwrap();
+/// println!("{} and {}", legacy.identity(), newest.identity());
+/// ```
+#[derive(Debug, Default)]
+pub struct DxcRegistry {
+    entries: Vec<(String, Dxc)>,
+}
+
+impl DxcRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Loads a library like [`Dxc::new()`] and adds it under `tag`.
+    pub fn load(&mut self, lib_path: Option<PathBuf>, tag: impl Into<String>) -> Result<&Dxc> {
+        let dxc = Dxc::new(lib_path)?;
+        Ok(self.add(dxc, tag))
+    }
+
+    /// Adds an already loaded library under `tag`, replacing a library previously
+    /// added with the same tag.
+    pub fn add(&mut self, dxc: Dxc, tag: impl Into<String>) -> &Dxc {
+        let tag = tag.into();
+        self.entries.retain(|(existing, _)| *existing != tag);
+        self.entries.push((tag, dxc));
+        &self.entries.last().unwrap().1
+    }
+
+    pub fn by_tag(&self, tag: &str) -> Option<&Dxc> {
+        self.entries
+            .iter()
+            .find(|(existing, _)| existing == tag)
+            .map(|(_, dxc)| dxc)
+    }
+
+    /// The newest library that is version `major.minor` or newer.
+    ///
+    /// Libraries that do not report their version are never selected.
+    pub fn with_min_version(&self, major: u32, minor: u32) -> Option<&Dxc> {
+        self.entries
+            .iter()
+            .filter_map(|(_, dxc)| Some((dxc, dxc.identity().version.as_ref()?)))
+            .filter(|(_, version)| version.is_at_least(major, minor))
+            .max_by_key(|(_, version)| version.ordering_key())
+            .map(|(dxc, _)| dxc)
+    }
+
+    /// All libraries in the order they were added, with their tag.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &Dxc)> {
+        self.entries.iter().map(|(tag, dxc)| (tag.as_str(), dxc))
+    }
+
+    /// Identities of all loaded libraries, in the order they were added.
+    pub fn identities(&self) -> impl Iterator<Item = &DxcIdentity> {
+        self.entries.iter().map(|(_, dxc)| dxc.identity())
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
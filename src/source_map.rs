@@ -0,0 +1,185 @@
+use crate::diagnostics::Diagnostic;
+
+/// Position in an original source file.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct SourceLocation {
+    pub file: String,
+    /// 1-based line.
+    pub line: u32,
+}
+
+/// Parses `#line 12 "file.hlsl"`, `#line 12` and the GNU `# 12 "file.hlsl" 2` form.
+fn parse_line_directive(line: &str) -> Option<(u32, Option<String>)> {
+    let directive = line.trim_start().strip_prefix('#')?.trim_start();
+    let directive = directive.strip_prefix("line").unwrap_or(directive);
+
+    let directive = directive.trim_start();
+    let digits = directive
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(directive.len());
+    if digits == 0 {
+        return None;
+    }
+    let number = directive[..digits].parse().ok()?;
+
+    let rest = directive[digits..].trim_start();
+    let file = rest.strip_prefix('"').and_then(unescape_filename);
+
+    Some((number, file))
+}
+
+/// Decodes the quoted filename at the start of `quoted` up to its closing quote, as
+/// escaped by the preprocessor. Only `\\` and `\"` are decoded, as Windows paths
+/// otherwise contain no escapes.
+fn unescape_filename(quoted: &str) -> Option<String> {
+    let mut file = String::new();
+    let mut chars = quoted.chars();
+    loop {
+        match chars.next()? {
+            '"' => return Some(file),
+            '\\' => match chars.next()? {
+                c @ ('\\' | '"') => file.push(c),
+                c => {
+                    file.push('\\');
+                    file.push(c);
+                }
+            },
+            c => file.push(c),
+        }
+    }
+}
+
+/// Maps every line of preprocessed output back to the file and line it came from,
+/// based on the `#line` directives in the output.
+///
+/// ```
+/// use hassle_rs::{SourceLocation, SourceMap};
+///
+/// let source_map = SourceMap::parse(
+///     "#line 1 \"main.hlsl\"\nfloat a;\n#line 3 \"common.hlsl\"\nfloat b;\nfloat c;\n",
+/// );
+///
+/// assert_eq!(source_map.lookup(1), None); // The `#line` directive itself
+/// assert_eq!(
+///     source_map.lookup(5),
+///     Some(&SourceLocation { file: "common.hlsl".to_owned(), line: 4 })
+/// );
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SourceMap {
+    lines: Vec<Option<SourceLocation>>,
+}
+
+impl SourceMap {
+    pub fn parse(preprocessed: &str) -> Self {
+        let mut lines = vec![];
+        let mut current: Option<SourceLocation> = None;
+
+        for line in preprocessed.lines() {
+            if let Some((number, file)) = parse_line_directive(line) {
+                let file = match (file, &current) {
+                    (Some(file), _) => Some(file),
+                    (None, Some(current)) => Some(current.file.clone()),
+                    (None, None) => None,
+                };
+                current = file.map(|file| SourceLocation { file, line: number });
+                lines.push(None);
+            } else {
+                lines.push(current.clone());
+                if let Some(current) = &mut current {
+                    current.line += 1;
+                }
+            }
+        }
+
+        Self { lines }
+    }
+
+    /// Original location of the 1-based `line` of the preprocessed output, `None` for
+    /// `#line` directives and lines before the first one.
+    pub fn lookup(&self, line: u32) -> Option<&SourceLocation> {
+        self.lines.get(line.checked_sub(1)? as usize)?.as_ref()
+    }
+
+    /// Number of lines in the preprocessed output.
+    pub fn len(&self) -> usize {
+        self.lines.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lines.is_empty()
+    }
+
+    /// Points `diagnostic` at the original file and line, for diagnostics reported
+    /// while compiling the preprocessed output under the name `preprocessed_name`.
+    ///
+    /// Diagnostics in other files are returned unchanged, as DXC follows the `#line`
+    /// directives and most diagnostics already point at the original location.
+    ///
+    /// ```
+    /// use hassle_rs::{parse_diagnostics, SourceMap};
+    ///
+    /// let source_map = SourceMap::parse(
+    ///     "#line 1 \"main.hlsl\"\nfloat a;\n#line 7 \"common.hlsl\"\nfloat b;\n",
+    /// );
+    /// let diagnostics = parse_diagnostics(
+    ///     "main.i:4:1: error: first\ncommon.hlsl:2:1: error: second\n",
+    /// );
+    ///
+    /// // Reported against the preprocessed output, moved to the original location
+    /// let remapped = source_map.remap(&diagnostics[0], "main.i");
+    /// assert_eq!(remapped.file.as_deref(), Some("common.hlsl"));
+    /// assert_eq!(remapped.line, Some(7));
+    ///
+    /// // Already reported at the original location, left as is
+    /// assert_eq!(source_map.remap(&diagnostics[1], "main.i"), diagnostics[1]);
+    /// ```
+    pub fn remap(&self, diagnostic: &Diagnostic, preprocessed_name: &str) -> Diagnostic {
+        let mut diagnostic = diagnostic.clone();
+        if diagnostic.file.as_deref() != Some(preprocessed_name) {
+            return diagnostic;
+        }
+
+        if let Some(location) = diagnostic.line.and_then(|line| self.lookup(line)) {
+            diagnostic.file = Some(location.file.clone());
+            diagnostic.line = Some(location.line);
+        }
+        diagnostic
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn escaped_filename() {
+        let source_map = SourceMap::parse(
+            "#line 1 \"C:\\\\shaders\\\\a.hlsl\"\nfloat a;\n# 7 \"say \\\"hi\\\".hlsl\" 2\nfloat b;\n",
+        );
+
+        assert_eq!(
+            source_map.lookup(2),
+            Some(&SourceLocation {
+                file: r"C:\shaders\a.hlsl".to_owned(),
+                line: 1
+            })
+        );
+        assert_eq!(
+            source_map.lookup(4),
+            Some(&SourceLocation {
+                file: "say \"hi\".hlsl".to_owned(),
+                line: 7
+            })
+        );
+    }
+
+    #[test]
+    fn quoted_filename() {
+        assert_eq!(parse_line_directive("#line 3 \"a.hlsl"), Some((3, None)));
+        assert_eq!(
+            parse_line_directive(r#"#line 3 "a\\b.hlsl" 1"#),
+            Some((3, Some(r"a\b.hlsl".to_owned())))
+        );
+    }
+}
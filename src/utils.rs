@@ -1,6 +1,11 @@
-use std::path::PathBuf;
+use std::any::Any;
+use std::path::{Path, PathBuf};
 
-use crate::os::{SysFreeString, SysStringLen, BSTR, HRESULT, LPCSTR, LPCWSTR, WCHAR};
+use crate::diagnostics::{parse_diagnostics, Diagnostic};
+use crate::os::{
+    CoTaskMemFree, SysFreeString, SysStringLen, BSTR, HRESULT, LPCSTR, LPCWSTR, LPWSTR, WCHAR,
+};
+use crate::source_map::SourceMap;
 use crate::wrapper::*;
 use thiserror::Error;
 
@@ -31,6 +36,17 @@ pub(crate) fn from_bstr(string: BSTR) -> String {
     }
 }
 
+/// Converts a string allocated with `CoTaskMemAlloc` and releases it.
+pub(crate) fn from_co_task_wide(string: LPWSTR) -> String {
+    if string.is_null() {
+        return String::new();
+    }
+
+    let result = from_wide(string);
+    unsafe { CoTaskMemFree(string.cast()) };
+    result
+}
+
 pub(crate) fn from_lpstr(string: LPCSTR) -> String {
     unsafe {
         let len = (0..).take_while(|&i| *string.offset(i) != 0).count();
@@ -40,19 +56,17 @@ pub(crate) fn from_lpstr(string: LPCSTR) -> String {
     }
 }
 
-struct DefaultIncludeHandler {}
+/// Loads includes relative to the current working directory.
+pub(crate) struct DefaultIncludeHandler {}
 
-impl DxcIncludeHandler for DefaultIncludeHandler {
-    fn load_source(&mut self, filename: String) -> Option<String> {
-        use std::io::Read;
-        match std::fs::File::open(filename) {
-            Ok(mut f) => {
-                let mut content = String::new();
-                f.read_to_string(&mut content).ok()?;
-                Some(content)
-            }
-            Err(_) => None,
-        }
+impl DxcIncludeHandler2 for DefaultIncludeHandler {
+    fn load_include(
+        &mut self,
+        filename: &str,
+        _includer: Option<&str>,
+    ) -> Result<DxcIncludeSource, DxcIncludeError> {
+        let data = std::fs::read(filename)?;
+        Ok(DxcIncludeSource::new(data, DxcIncludeEncoding::Unknown))
     }
 }
 
@@ -64,20 +78,59 @@ pub enum HassleError {
     CompileError(String),
     #[error("Validation error: {0}")]
     ValidationError(String),
-    #[error("Failed to load library {filename:?}: {inner:?}")]
+    #[error("Assemble error: {0}")]
+    AssembleError(String),
+    #[error("Failed to load library, tried:{}", format_load_attempts(.tried))]
     LoadLibraryError {
-        filename: PathBuf,
-        #[source]
-        inner: libloading::Error,
+        /// Every path that was tried, with the reason it could not be used.
+        tried: Vec<(PathBuf, libloading::Error)>,
     },
     #[error("LibLoading error: {0:?}")]
     LibLoadingError(#[from] libloading::Error),
+    #[error("Permutation {permutation}: {inner}")]
+    PermutationError {
+        permutation: String,
+        #[source]
+        inner: Box<HassleError>,
+    },
+    #[error("Compilation timed out")]
+    Timeout,
+    #[error("Compilation was cancelled")]
+    Cancelled,
+    #[error("Include handler panicked: {0}")]
+    IncludeHandlerPanic(String),
+    #[error("IO error: {0}")]
+    IoError(#[from] std::io::Error),
+    #[error("Invalid target profile: {0}")]
+    InvalidTargetProfile(String),
     #[error("Windows only")]
     WindowsOnly(String),
 }
 
 pub type Result<T, E = HassleError> = std::result::Result<T, E>;
 
+fn format_load_attempts(tried: &[(PathBuf, libloading::Error)]) -> String {
+    tried
+        .iter()
+        .map(|(path, error)| format!("\n  {:?}: {}", path, error))
+        .collect()
+}
+
+impl HassleError {
+    /// Structured diagnostics parsed from the compiler, validator or assembler output of a
+    /// [`HassleError::CompileError`], [`HassleError::ValidationError`] or
+    /// [`HassleError::AssembleError`], also when wrapped in a [`HassleError::PermutationError`].
+    pub fn diagnostics(&self) -> Vec<Diagnostic> {
+        match self {
+            Self::CompileError(text) | Self::ValidationError(text) | Self::AssembleError(text) => {
+                parse_diagnostics(text)
+            }
+            Self::PermutationError { inner, .. } => inner.diagnostics(),
+            _ => vec![],
+        }
+    }
+}
+
 impl HRESULT {
     /// Turns an [`HRESULT`] from the COM [`crate::ffi`] API declaration
     /// into a [`Result`] containing [`HassleError`].
@@ -108,6 +161,11 @@ impl HRESULT {
 /// this function expects `dxcompiler.dll` to be available in the current
 /// executable environment.
 ///
+/// `target_profile` can be a string such as `"cs_6_1"` or a [`crate::ShaderProfile`].
+///
+/// Includes are loaded through `include_handler`, such as a [`crate::FileIncludeHandler`],
+/// or relative to the current working directory when `None` is passed.
+///
 /// Specify -spirv as one of the `args` to compile to SPIR-V
 /// `dxc_path` can point to a library directly or the directory containing the library,
 /// in which case the appended filename depends on the platform.
@@ -115,10 +173,128 @@ pub fn compile_hlsl(
     source_name: &str,
     shader_text: &str,
     entry_point: &str,
-    target_profile: &str,
+    target_profile: impl Into<String>,
     args: &[&str],
+    include_handler: Option<&mut dyn DxcIncludeHandler2>,
     defines: &[(&str, Option<&str>)],
 ) -> Result<Vec<u8>> {
+    compile_hlsl_with_diagnostics(
+        source_name,
+        shader_text,
+        entry_point,
+        target_profile,
+        args,
+        include_handler,
+        defines,
+    )
+    .map(|(data, _)| data)
+}
+
+/// Same as [`compile_hlsl()`], but also returns the warnings and notes emitted
+/// by a successful compilation.
+///
+/// On failure the diagnostics are available through [`HassleError::diagnostics()`].
+pub fn compile_hlsl_with_diagnostics(
+    source_name: &str,
+    shader_text: &str,
+    entry_point: &str,
+    target_profile: impl Into<String>,
+    args: &[&str],
+    include_handler: Option<&mut dyn DxcIncludeHandler2>,
+    defines: &[(&str, Option<&str>)],
+) -> Result<(Vec<u8>, Vec<Diagnostic>)> {
+    compile_hlsl_impl(
+        source_name,
+        shader_text,
+        entry_point,
+        target_profile.into(),
+        args,
+        include_handler,
+        defines,
+        false,
+    )
+}
+
+/// Same as [`compile_hlsl()`], but treats any warning emitted by the compiler as a failure.
+///
+/// The warnings are available through [`HassleError::diagnostics()`].
+pub fn compile_hlsl_strict(
+    source_name: &str,
+    shader_text: &str,
+    entry_point: &str,
+    target_profile: impl Into<String>,
+    args: &[&str],
+    include_handler: Option<&mut dyn DxcIncludeHandler2>,
+    defines: &[(&str, Option<&str>)],
+) -> Result<Vec<u8>> {
+    compile_hlsl_impl(
+        source_name,
+        shader_text,
+        entry_point,
+        target_profile.into(),
+        args,
+        include_handler,
+        defines,
+        true,
+    )
+    .map(|(data, _)| data)
+}
+
+/// Same as [`compile_hlsl()`], but loads the shader from `path` and passes it to the
+/// compiler as the source name, so that diagnostics point at the file.
+///
+/// The directory of the file is searched for includes before any other include
+/// directory in `args`.
+pub fn compile_hlsl_file(
+    path: impl AsRef<Path>,
+    entry_point: &str,
+    target_profile: impl Into<String>,
+    args: &[&str],
+    include_handler: Option<&mut dyn DxcIncludeHandler2>,
+    defines: &[(&str, Option<&str>)],
+) -> Result<Vec<u8>> {
+    let path = path.as_ref();
+    let source_name = path.to_string_lossy();
+    let include_dir = match path.parent() {
+        Some(dir) if dir != Path::new("") => dir.to_string_lossy(),
+        _ => ".".into(),
+    };
+
+    let mut file_args = vec!["-I", &include_dir];
+    file_args.extend_from_slice(args);
+
+    let dxc = Dxc::new(None)?;
+
+    let compiler = dxc.create_compiler()?;
+    let library = dxc.create_library()?;
+
+    let blob = library.create_blob_from_file(path, None)?;
+
+    compile_hlsl_with_compiler(
+        &compiler,
+        &library,
+        &blob,
+        &source_name,
+        entry_point,
+        target_profile.into(),
+        &file_args,
+        include_handler,
+        defines,
+        false,
+    )
+    .map(|(data, _)| data)
+}
+
+fn compile_hlsl_impl(
+    source_name: &str,
+    shader_text: &str,
+    entry_point: &str,
+    target_profile: String,
+    args: &[&str],
+    include_handler: Option<&mut dyn DxcIncludeHandler2>,
+    defines: &[(&str, Option<&str>)],
+    strict: bool,
+) -> Result<(Vec<u8>, Vec<Diagnostic>)> {
     let dxc = Dxc::new(None)?;
 
     let compiler = dxc.create_compiler()?;
@@ -126,27 +302,117 @@ pub fn compile_hlsl(
 
     let blob = library.create_blob_with_encoding_from_str(shader_text)?;
 
-    let result = compiler.compile(
+    compile_hlsl_with_compiler(
+        &compiler,
+        &library,
         &blob,
         source_name,
         entry_point,
         target_profile,
         args,
-        Some(&mut DefaultIncludeHandler {}),
+        include_handler,
+        defines,
+        strict,
+    )
+}
+
+/// Message of a caught panic, for [`HassleError::IncludeHandlerPanic`].
+pub(crate) fn panic_message(panic: &(dyn Any + Send)) -> String {
+    if let Some(message) = panic.downcast_ref::<&str>() {
+        (*message).to_owned()
+    } else if let Some(message) = panic.downcast_ref::<String>() {
+        message.clone()
+    } else {
+        "unknown panic payload".to_owned()
+    }
+}
+
+/// Body of [`compile_hlsl()`] and its variants, with an already created compiler.
+pub(crate) fn compile_hlsl_with_compiler(
+    compiler: &DxcCompiler,
+    library: &DxcLibrary,
+    blob: &DxcBlobEncoding,
+    source_name: &str,
+    entry_point: &str,
+    target_profile: String,
+    args: &[&str],
+    include_handler: Option<&mut dyn DxcIncludeHandler2>,
+    defines: &[(&str, Option<&str>)],
+    strict: bool,
+) -> Result<(Vec<u8>, Vec<Diagnostic>)> {
+    let mut default_include_handler = DefaultIncludeHandler {};
+    let include_handler = include_handler.unwrap_or(&mut default_include_handler);
+
+    let result = compiler.compile(
+        blob,
+        source_name,
+        entry_point,
+        target_profile,
+        args,
+        Some(include_handler),
         defines,
     );
 
     match result {
-        Err(result) => {
-            let error_blob = result.0.get_error_buffer()?;
+        Err((_, error @ HassleError::IncludeHandlerPanic(_))) => Err(error),
+        Err((result, _)) => {
+            let error_blob = result.get_error_buffer()?;
             Err(HassleError::CompileError(
                 library.get_blob_as_string(&error_blob.into())?,
             ))
         }
         Ok(result) => {
+            let diagnostics = result.get_diagnostics()?;
+
+            if strict && diagnostics.iter().any(Diagnostic::is_warning) {
+                let error_blob = result.get_error_buffer()?;
+                return Err(HassleError::CompileError(
+                    library.get_blob_as_string(&error_blob.into())?,
+                ));
+            }
+
             let result_blob = result.get_result()?;
 
-            Ok(result_blob.to_vec())
+            Ok((result_blob.to_vec(), diagnostics))
+        }
+    }
+}
+
+/// Helper function to run only the preprocessor on a HLSL shader, this function
+/// expects `dxcompiler.dll` to be available in the current executable environment.
+///
+/// Returns the preprocessed text and a [`SourceMap`] that maps each of its lines back
+/// to the original file and line. Includes are loaded the same way as in [`compile_hlsl()`].
+pub fn preprocess_hlsl(
+    source_name: &str,
+    shader_text: &str,
+    args: &[&str],
+    include_handler: Option<&mut dyn DxcIncludeHandler2>,
+    defines: &[(&str, Option<&str>)],
+) -> Result<(String, SourceMap)> {
+    let dxc = Dxc::new(None)?;
+
+    let compiler = dxc.create_compiler()?;
+    let library = dxc.create_library()?;
+
+    let blob = library.create_blob_with_encoding_from_str(shader_text)?;
+
+    let mut default_include_handler = DefaultIncludeHandler {};
+    let include_handler = include_handler.unwrap_or(&mut default_include_handler);
+
+    match compiler.preprocess(&blob, source_name, args, Some(include_handler), defines) {
+        Err((_, error @ HassleError::IncludeHandlerPanic(_))) => Err(error),
+        Err((result, _)) => {
+            let error_blob = result.get_error_buffer()?;
+            Err(HassleError::CompileError(
+                library.get_blob_as_string(&error_blob.into())?,
+            ))
+        }
+        Ok(result) => {
+            let text = library.get_blob_as_string(&result.get_result()?)?;
+            let source_map = SourceMap::parse(&text);
+
+            Ok((text, source_map))
         }
     }
 }
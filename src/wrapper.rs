@@ -4,22 +4,55 @@
     clippy::type_complexity
 )]
 
+use crate::com_object::{ComClass, ComObject, ComObjectRef, E_FAIL, E_POINTER};
+use crate::diagnostics::{parse_diagnostics, Diagnostic};
 use crate::ffi::*;
-use crate::os::{HRESULT, LPCWSTR, LPWSTR, WCHAR};
-use crate::utils::{from_wide, to_wide, HassleError, Result};
+use crate::include::IncludeTracker;
+#[cfg(windows)]
+use crate::loader::DXIL_LIB_NAMES;
+use crate::loader::{load_library, DXCOMPILER_LIB_NAMES, DXC_PATH_ENV};
+use crate::os::{CoTaskMemFree, HRESULT, LPCWSTR, LPWSTR, WCHAR};
+use crate::utils::{
+    from_co_task_wide, from_lpstr, from_wide, panic_message, to_wide, HassleError, Result,
+};
 use com_rs::ComPtr;
 use libloading::{Library, Symbol};
+use std::any::Any;
+use std::panic::AssertUnwindSafe;
 use std::path::{Path, PathBuf};
 use std::pin::Pin;
+use std::sync::Arc;
+
+// DXC objects are free-threaded and atomically reference counted, so they can be moved
+// to another thread. They are not `Sync`: a single object must not be used from multiple
+// threads at once, create a compiler per thread from a shared `Dxc` instead.
+unsafe impl Send for DxcBlob {}
+unsafe impl Send for DxcBlobEncoding {}
+unsafe impl Send for DxcOperationResult {}
+unsafe impl Send for DxcResult {}
+unsafe impl Send for DxcCompiler {}
+unsafe impl Send for DxcCompiler3 {}
+unsafe impl Send for DxcLinker {}
+unsafe impl Send for DxcAssembler {}
+unsafe impl Send for DxcOptimizer {}
+unsafe impl Send for DxcContainerBuilder {}
+unsafe impl Send for DxcLibrary {}
+unsafe impl Send for DxcValidator {}
+unsafe impl Send for DxcReflector {}
+
+// Every object holds a handle to the library its vtable points into. The handle is
+// declared after `inner`, so that the object is released before the library can be
+// unloaded when the last handle is dropped.
 
 #[derive(Debug)]
 pub struct DxcBlob {
     inner: ComPtr<IDxcBlob>,
+    lib: Arc<Library>,
 }
 
 impl DxcBlob {
-    fn new(inner: ComPtr<IDxcBlob>) -> Self {
-        Self { inner }
+    fn new(inner: ComPtr<IDxcBlob>, lib: Arc<Library>) -> Self {
+        Self { inner, lib }
     }
 
     pub fn as_slice<T>(&self) -> &[T] {
@@ -63,28 +96,127 @@ impl AsMut<[u8]> for DxcBlob {
 #[derive(Debug)]
 pub struct DxcBlobEncoding {
     inner: ComPtr<IDxcBlobEncoding>,
+    lib: Arc<Library>,
 }
 
 impl DxcBlobEncoding {
-    fn new(inner: ComPtr<IDxcBlobEncoding>) -> Self {
-        Self { inner }
+    fn new(inner: ComPtr<IDxcBlobEncoding>, lib: Arc<Library>) -> Self {
+        Self { inner, lib }
+    }
+
+    /// Decodes the contents as text, assuming UTF-8 unless the blob is tagged as UTF-16.
+    pub(crate) fn to_string_lossy(&self) -> String {
+        const CP_UTF16: u32 = 1200;
+
+        if self.inner.is_null() {
+            return String::new();
+        }
+
+        let mut known = 0u32;
+        let mut code_page = 0u32;
+        let encoding_hr = unsafe { self.inner.get_encoding(&mut known, &mut code_page) };
+
+        let blob = DxcBlob::new((&self.inner).into(), self.lib.clone());
+        if !encoding_hr.is_err() && known != 0 && code_page == CP_UTF16 {
+            String::from_utf16_lossy(blob.as_slice::<u16>())
+        } else {
+            String::from_utf8_lossy(blob.as_slice::<u8>()).into_owned()
+        }
+        .trim_end_matches('\0')
+        .to_owned()
     }
 }
 
 impl From<DxcBlobEncoding> for DxcBlob {
     fn from(encoded_blob: DxcBlobEncoding) -> Self {
-        DxcBlob::new((&encoded_blob.inner).into())
+        DxcBlob::new((&encoded_blob.inner).into(), encoded_blob.lib)
     }
 }
 
 #[derive(Debug)]
 pub struct DxcOperationResult {
     inner: ComPtr<IDxcOperationResult>,
+    compiler: Option<Box<DxcIdentity>>,
+    lib: Arc<Library>,
 }
 
 impl DxcOperationResult {
-    fn new(inner: ComPtr<IDxcOperationResult>) -> Self {
-        Self { inner }
+    fn new(inner: ComPtr<IDxcOperationResult>, lib: Arc<Library>) -> Self {
+        Self {
+            inner,
+            compiler: None,
+            lib,
+        }
+    }
+
+    fn produced_by(mut self, compiler: &DxcIdentity) -> Self {
+        self.compiler = Some(Box::new(compiler.clone()));
+        self
+    }
+
+    /// The library that produced this result, for results of a [`DxcCompiler`] or [`DxcLinker`].
+    pub fn compiler(&self) -> Option<&DxcIdentity> {
+        self.compiler.as_deref()
+    }
+
+    pub fn get_status(&self) -> Result<u32> {
+        let mut status: u32 = 0;
+        unsafe { self.inner.get_status(&mut status) }.result_with_success(status)
+    }
+
+    pub fn get_result(&self) -> Result<DxcBlob> {
+        let mut blob: ComPtr<IDxcBlob> = ComPtr::new();
+        unsafe { self.inner.get_result(blob.as_mut_ptr()) }.result()?;
+        Ok(DxcBlob::new(blob, self.lib.clone()))
+    }
+
+    pub fn get_error_buffer(&self) -> Result<DxcBlobEncoding> {
+        let mut blob: ComPtr<IDxcBlobEncoding> = ComPtr::new();
+
+        unsafe { self.inner.get_error_buffer(blob.as_mut_ptr()) }.result()?;
+        Ok(DxcBlobEncoding::new(blob, self.lib.clone()))
+    }
+
+    /// Errors, warnings and notes from the error buffer, available for both
+    /// failed and successful operations.
+    pub fn get_diagnostics(&self) -> Result<Vec<Diagnostic>> {
+        Ok(parse_diagnostics(
+            &self.get_error_buffer()?.to_string_lossy(),
+        ))
+    }
+
+    /// Warnings emitted by an operation that otherwise succeeded.
+    pub fn get_warnings(&self) -> Result<Vec<Diagnostic>> {
+        let mut diagnostics = self.get_diagnostics()?;
+        diagnostics.retain(Diagnostic::is_warning);
+        Ok(diagnostics)
+    }
+}
+
+#[derive(Debug)]
+pub struct DxcResult {
+    inner: ComPtr<IDxcResult>,
+    compiler: Option<Box<DxcIdentity>>,
+    lib: Arc<Library>,
+}
+
+impl DxcResult {
+    fn new(inner: ComPtr<IDxcResult>, lib: Arc<Library>) -> Self {
+        Self {
+            inner,
+            compiler: None,
+            lib,
+        }
+    }
+
+    fn produced_by(mut self, compiler: &DxcIdentity) -> Self {
+        self.compiler = Some(Box::new(compiler.clone()));
+        self
+    }
+
+    /// The library that produced this result.
+    pub fn compiler(&self) -> Option<&DxcIdentity> {
+        self.compiler.as_deref()
     }
 
     pub fn get_status(&self) -> Result<u32> {
@@ -95,14 +227,155 @@ impl DxcOperationResult {
     pub fn get_result(&self) -> Result<DxcBlob> {
         let mut blob: ComPtr<IDxcBlob> = ComPtr::new();
         unsafe { self.inner.get_result(blob.as_mut_ptr()) }.result()?;
-        Ok(DxcBlob::new(blob))
+        Ok(DxcBlob::new(blob, self.lib.clone()))
     }
 
     pub fn get_error_buffer(&self) -> Result<DxcBlobEncoding> {
         let mut blob: ComPtr<IDxcBlobEncoding> = ComPtr::new();
 
         unsafe { self.inner.get_error_buffer(blob.as_mut_ptr()) }.result()?;
-        Ok(DxcBlobEncoding::new(blob))
+        Ok(DxcBlobEncoding::new(blob, self.lib.clone()))
+    }
+
+    /// Errors, warnings and notes from [`DxcResult::errors()`], available for both
+    /// failed and successful compilations.
+    pub fn get_diagnostics(&self) -> Result<Vec<Diagnostic>> {
+        Ok(self
+            .errors()?
+            .map(|errors| parse_diagnostics(&errors))
+            .unwrap_or_default())
+    }
+
+    /// Warnings emitted by a compilation that otherwise succeeded.
+    pub fn get_warnings(&self) -> Result<Vec<Diagnostic>> {
+        let mut diagnostics = self.get_diagnostics()?;
+        diagnostics.retain(Diagnostic::is_warning);
+        Ok(diagnostics)
+    }
+
+    pub fn has_output(&self, kind: u32) -> bool {
+        unsafe { self.inner.has_output(kind) != 0 }
+    }
+
+    /// Kinds of all outputs stored in this result, see the `DXC_OUT_*` constants.
+    pub fn output_kinds(&self) -> Vec<u32> {
+        let num_outputs = unsafe { self.inner.get_num_outputs() };
+        (0..num_outputs)
+            .map(|i| unsafe { self.inner.get_output_by_index(i) })
+            .collect()
+    }
+
+    pub fn primary_output(&self) -> u32 {
+        unsafe { self.inner.primary_output() }
+    }
+
+    fn get_output<T: com_rs::ComInterface>(
+        &self,
+        kind: u32,
+    ) -> Result<Option<(ComPtr<T>, Option<String>)>> {
+        if !self.has_output(kind) {
+            return Ok(None);
+        }
+
+        let mut output: ComPtr<T> = ComPtr::new();
+        let mut output_name: ComPtr<IDxcBlobUtf16> = ComPtr::new();
+        unsafe {
+            self.inner.get_output(
+                kind,
+                &T::iid(),
+                output.as_mut_ptr::<std::ffi::c_void>(),
+                output_name.as_mut_ptr(),
+            )
+        }
+        .result()?;
+
+        if output.is_null() {
+            return Ok(None);
+        }
+
+        let output_name = if output_name.is_null() {
+            None
+        } else {
+            Some(from_wide(unsafe { output_name.get_string_pointer() }))
+        };
+
+        Ok(Some((output, output_name)))
+    }
+
+    fn get_output_blob(&self, kind: u32) -> Result<Option<DxcBlob>> {
+        Ok(self
+            .get_output::<IDxcBlob>(kind)?
+            .map(|(blob, _)| DxcBlob::new(blob, self.lib.clone())))
+    }
+
+    /// Text outputs are `IDxcBlobUtf8` or `IDxcBlobUtf16`, depending on the `-encoding` argument.
+    fn get_output_text(&self, kind: u32) -> Result<Option<String>> {
+        if let Ok(text) = self.get_output::<IDxcBlobUtf8>(kind) {
+            return Ok(text.map(|(text, _)| {
+                let text = unsafe {
+                    std::slice::from_raw_parts(
+                        text.get_string_pointer().cast::<u8>(),
+                        text.get_string_length(),
+                    )
+                };
+                String::from_utf8_lossy(text).into_owned()
+            }));
+        }
+
+        Ok(self.get_output::<IDxcBlobUtf16>(kind)?.map(|(text, _)| {
+            unsafe {
+                widestring::WideStr::from_ptr(text.get_string_pointer(), text.get_string_length())
+            }
+            .to_string_lossy()
+        }))
+    }
+
+    /// The compiled shader or library object, [`DXC_OUT_OBJECT`].
+    pub fn object(&self) -> Result<Option<DxcBlob>> {
+        self.get_output_blob(DXC_OUT_OBJECT)
+    }
+
+    /// Warnings and errors emitted by the compiler, [`DXC_OUT_ERRORS`].
+    pub fn errors(&self) -> Result<Option<String>> {
+        self.get_output_text(DXC_OUT_ERRORS)
+    }
+
+    /// Debug information and its suggested filename, [`DXC_OUT_PDB`].
+    ///
+    /// Only present when compiling with `-Zi`.
+    pub fn pdb(&self) -> Result<Option<(String, DxcBlob)>> {
+        Ok(self
+            .get_output::<IDxcBlob>(DXC_OUT_PDB)?
+            .map(|(blob, name)| {
+                (
+                    name.unwrap_or_default(),
+                    DxcBlob::new(blob, self.lib.clone()),
+                )
+            }))
+    }
+
+    /// Serialized reflection data, [`DXC_OUT_REFLECTION`].
+    ///
+    /// Only present when reflection is stripped from the object with `-Qstrip_reflect`.
+    pub fn reflection(&self) -> Result<Option<DxcBlob>> {
+        self.get_output_blob(DXC_OUT_REFLECTION)
+    }
+
+    /// Hash of the shader, optionally including its source, [`DXC_OUT_SHADER_HASH`].
+    pub fn shader_hash(&self) -> Result<Option<DxcShaderHash>> {
+        Ok(self
+            .get_output_blob(DXC_OUT_SHADER_HASH)?
+            .and_then(|blob| blob.as_slice::<DxcShaderHash>().first().copied()))
+    }
+
+    /// Serialized root signature, [`DXC_OUT_ROOT_SIGNATURE`].
+    pub fn root_signature(&self) -> Result<Option<DxcBlob>> {
+        self.get_output_blob(DXC_OUT_ROOT_SIGNATURE)
+    }
+
+    /// Disassembly text, [`DXC_OUT_DISASSEMBLY`].
+    pub fn disassembly(&self) -> Result<Option<String>> {
+        self.get_output_text(DXC_OUT_DISASSEMBLY)
     }
 }
 
@@ -110,74 +383,236 @@ pub trait DxcIncludeHandler {
     fn load_source(&mut self, filename: String) -> Option<String>;
 }
 
-#[repr(C)]
-struct DxcIncludeHandlerWrapperVtbl {
-    query_interface: extern "system" fn(
-        *const com_rs::IUnknown,
-        &com_rs::IID,
-        *mut *mut core::ffi::c_void,
-    ) -> HRESULT,
-    add_ref: extern "system" fn(*const com_rs::IUnknown) -> HRESULT,
-    release: extern "system" fn(*const com_rs::IUnknown) -> HRESULT,
-    #[cfg(not(windows))]
-    complete_object_destructor: extern "system" fn(*const com_rs::IUnknown) -> HRESULT,
-    #[cfg(not(windows))]
-    deleting_destructor: extern "system" fn(*const com_rs::IUnknown) -> HRESULT,
-    load_source: extern "system" fn(*mut com_rs::IUnknown, LPCWSTR, *mut *mut IDxcBlob) -> HRESULT,
+/// Encoding of the contents returned by a [`DxcIncludeHandler2`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DxcIncludeEncoding {
+    Utf8,
+    /// UTF-16 in native byte order.
+    Utf16,
+    /// No code page, the compiler detects the encoding from a byte order mark.
+    Unknown,
+}
+
+impl DxcIncludeEncoding {
+    fn code_page(self) -> u32 {
+        match self {
+            Self::Utf8 => 65001,
+            Self::Utf16 => 1200,
+            Self::Unknown => 0,
+        }
+    }
+}
+
+/// Contents of an included file.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DxcIncludeSource {
+    pub data: Vec<u8>,
+    pub encoding: DxcIncludeEncoding,
+    /// Where the file was actually loaded from, if it differs from the requested filename,
+    /// used by [`crate::DependencyTracker`].
+    pub path: Option<PathBuf>,
+}
+
+impl DxcIncludeSource {
+    pub fn new(data: Vec<u8>, encoding: DxcIncludeEncoding) -> Self {
+        Self {
+            data,
+            encoding,
+            path: None,
+        }
+    }
+
+    pub fn with_path(mut self, path: impl Into<PathBuf>) -> Self {
+        self.path = Some(path.into());
+        self
+    }
+
+    fn validate(&self, filename: &str) -> Result<(), DxcIncludeError> {
+        match self.encoding {
+            DxcIncludeEncoding::Utf8 => std::str::from_utf8(&self.data).map(|_| ()).map_err(|e| {
+                DxcIncludeError::Failed(format!("{} is not valid UTF-8: {}", filename, e))
+            }),
+            DxcIncludeEncoding::Utf16 if self.data.len() & 1 != 0 => Err(DxcIncludeError::Failed(
+                format!("{} is not valid UTF-16: odd number of bytes", filename),
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    fn to_string_lossy(&self) -> String {
+        match self.encoding {
+            DxcIncludeEncoding::Utf16 => {
+                let wide = self
+                    .data
+                    .chunks_exact(2)
+                    .map(|c| u16::from_ne_bytes([c[0], c[1]]))
+                    .collect::<Vec<_>>();
+                String::from_utf16_lossy(&wide)
+            }
+            _ => String::from_utf8_lossy(&self.data).into_owned(),
+        }
+    }
+}
+
+impl From<String> for DxcIncludeSource {
+    fn from(source: String) -> Self {
+        Self::new(source.into_bytes(), DxcIncludeEncoding::Utf8)
+    }
+}
+
+impl From<&str> for DxcIncludeSource {
+    fn from(source: &str) -> Self {
+        Self::from(source.to_owned())
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum DxcIncludeError {
+    /// Reported to the compiler as `ERROR_FILE_NOT_FOUND`, so that it continues
+    /// searching the remaining include directories.
+    NotFound,
+    /// Reported as an `#error` in place of the included file, so that the reason
+    /// shows up in the compiler diagnostics at the `#include`.
+    Failed(String),
+}
+
+impl From<std::io::Error> for DxcIncludeError {
+    fn from(error: std::io::Error) -> Self {
+        if error.kind() == std::io::ErrorKind::NotFound {
+            Self::NotFound
+        } else {
+            Self::Failed(error.to_string())
+        }
+    }
+}
+
+/// Extended version of [`DxcIncludeHandler`] that can report why an include failed,
+/// return contents in other encodings than UTF-8 and knows which file contains the `#include`.
+///
+/// Every [`DxcIncludeHandler`] implements this trait, so both can be passed to the compiler.
+///
+/// A panic in the handler is caught before it reaches the compiler, which then stops
+/// loading includes, and is returned from the compile call as
+/// [`HassleError::IncludeHandlerPanic`].
+pub trait DxcIncludeHandler2 {
+    /// `filename` is the include joined with the directory of the including file, as
+    /// passed by the compiler. `includer` is the path of the including file, `None`
+    /// when it could not be determined.
+    fn load_include(
+        &mut self,
+        filename: &str,
+        includer: Option<&str>,
+    ) -> Result<DxcIncludeSource, DxcIncludeError>;
+}
+
+impl<T: DxcIncludeHandler + ?Sized> DxcIncludeHandler2 for T {
+    fn load_include(
+        &mut self,
+        filename: &str,
+        _includer: Option<&str>,
+    ) -> Result<DxcIncludeSource, DxcIncludeError> {
+        self.load_source(filename.to_owned())
+            .map(DxcIncludeSource::from)
+            .ok_or(DxcIncludeError::NotFound)
+    }
 }
 
 #[repr(C)]
+struct DxcIncludeHandlerMethods {
+    load_source:
+        extern "system" fn(*const com_rs::IUnknown, LPCWSTR, *mut *mut IDxcBlob) -> HRESULT,
+}
+
 struct DxcIncludeHandlerWrapper<'a, 'i> {
-    vtable: Box<DxcIncludeHandlerWrapperVtbl>,
-    handler: &'i mut dyn DxcIncludeHandler,
-    pinned: Vec<Pin<String>>,
+    handler: &'i mut dyn DxcIncludeHandler2,
+    pinned: Vec<Pin<Vec<u8>>>,
+    includes: IncludeTracker,
     library: &'a DxcLibrary,
+    /// Payload of a panic in `handler`, caught before it could unwind into the compiler.
+    panic: Option<Box<dyn Any + Send>>,
 }
 
-impl<'a, 'i> DxcIncludeHandlerWrapper<'a, 'i> {
-    extern "system" fn query_interface(
-        _me: *const com_rs::IUnknown,
-        _rrid: &com_rs::IID,
-        _ppv_obj: *mut *mut core::ffi::c_void,
-    ) -> HRESULT {
-        HRESULT(0) // dummy impl
+impl<'a, 'i> ComClass for DxcIncludeHandlerWrapper<'a, 'i> {
+    type Methods = DxcIncludeHandlerMethods;
+
+    fn methods() -> Self::Methods {
+        DxcIncludeHandlerMethods {
+            load_source: Self::load_source,
+        }
     }
 
-    extern "system" fn dummy(_me: *const com_rs::IUnknown) -> HRESULT {
-        HRESULT(0) // dummy impl
+    fn implements(iid: &com_rs::IID) -> bool {
+        *iid == IID_IDxcIncludeHandler
     }
+}
 
+impl<'a, 'i> DxcIncludeHandlerWrapper<'a, 'i> {
     extern "system" fn load_source(
-        me: *mut com_rs::IUnknown,
+        this: *const com_rs::IUnknown,
         filename: LPCWSTR,
         include_source: *mut *mut IDxcBlob,
     ) -> HRESULT {
-        let me = me.cast::<DxcIncludeHandlerWrapper>();
+        if filename.is_null() || include_source.is_null() {
+            return E_POINTER;
+        }
 
-        let filename = crate::utils::from_wide(filename);
+        unsafe {
+            ComObject::<Self>::with_inner(this, |me| {
+                if me.panic.is_some() {
+                    return E_FAIL;
+                }
+
+                // Decoding the filename may panic as well
+                match std::panic::catch_unwind(AssertUnwindSafe(|| {
+                    me.load_include(&crate::utils::from_wide(filename), include_source)
+                })) {
+                    Ok(hr) => hr,
+                    Err(panic) => {
+                        me.panic = Some(panic);
+                        E_FAIL
+                    }
+                }
+            })
+        }
+        .unwrap_or(E_FAIL)
+    }
 
-        let source = unsafe { (*me).handler.load_source(filename) };
+    fn load_include(&mut self, filename: &str, include_source: *mut *mut IDxcBlob) -> HRESULT {
+        let includer = self.includes.find_includer(filename);
 
-        if let Some(source) = source {
-            let source = Pin::new(source);
-            let mut blob = unsafe {
-                (*me)
-                    .library
-                    .create_blob_with_encoding_from_str(&source)
-                    .unwrap()
-            };
+        let source = self
+            .handler
+            .load_include(filename, includer.as_deref())
+            .and_then(|source| source.validate(filename).map(|_| source));
 
-            unsafe {
-                blob.inner.add_ref();
-                *include_source = *blob.inner.as_mut_ptr();
-                (*me).pinned.push(source);
+        let source = match source {
+            Ok(source) => source,
+            Err(DxcIncludeError::NotFound) => {
+                return HRESULT(-2_147_024_894); // ERROR_FILE_NOT_FOUND / 0x80070002
             }
+            Err(DxcIncludeError::Failed(reason)) => DxcIncludeSource::from(format!(
+                "#error {}\n",
+                reason.replace(&['\r', '\n'][..], " ")
+            )),
+        };
 
-            0
-        } else {
-            -2_147_024_894 // ERROR_FILE_NOT_FOUND / 0x80070002
+        self.includes.track(filename, &source.to_string_lossy());
+
+        let code_page = source.encoding.code_page();
+        let data = Pin::new(source.data);
+        let mut blob = match self.library.create_blob_with_code_page(&data, code_page) {
+            Ok(blob) => blob,
+            Err(HassleError::Win32Error(hr)) => return hr,
+            Err(_) => return E_FAIL,
+        };
+
+        unsafe {
+            blob.inner.add_ref();
+            *include_source = *blob.inner.as_mut_ptr();
         }
-        .into()
+        self.pinned.push(data);
+
+        HRESULT(0)
     }
 }
 
@@ -185,11 +620,27 @@ impl<'a, 'i> DxcIncludeHandlerWrapper<'a, 'i> {
 pub struct DxcCompiler {
     inner: ComPtr<IDxcCompiler2>,
     library: DxcLibrary,
+    identity: DxcIdentity,
+    lib: Arc<Library>,
 }
 
 impl DxcCompiler {
-    fn new(inner: ComPtr<IDxcCompiler2>, library: DxcLibrary) -> Self {
-        Self { inner, library }
+    fn new(
+        inner: ComPtr<IDxcCompiler2>,
+        library: DxcLibrary,
+        identity: DxcIdentity,
+        lib: Arc<Library>,
+    ) -> Self {
+        Self {
+            inner,
+            library,
+            identity,
+            lib,
+        }
+    }
+
+    pub fn identity(&self) -> &DxcIdentity {
+        &self.identity
     }
 
     fn prep_defines(
@@ -225,41 +676,47 @@ impl DxcCompiler {
 
     fn prep_include_handler<'a, 'i>(
         library: &'a DxcLibrary,
-        include_handler: Option<&'i mut dyn DxcIncludeHandler>,
-    ) -> Option<Box<DxcIncludeHandlerWrapper<'a, 'i>>> {
+        include_handler: Option<&'i mut dyn DxcIncludeHandler2>,
+        source_name: &str,
+        blob: &DxcBlobEncoding,
+    ) -> Option<ComObjectRef<DxcIncludeHandlerWrapper<'a, 'i>>> {
         if let Some(include_handler) = include_handler {
-            let vtable = DxcIncludeHandlerWrapperVtbl {
-                query_interface: DxcIncludeHandlerWrapper::query_interface,
-                add_ref: DxcIncludeHandlerWrapper::dummy,
-                release: DxcIncludeHandlerWrapper::dummy,
-                #[cfg(not(windows))]
-                complete_object_destructor: DxcIncludeHandlerWrapper::dummy,
-                #[cfg(not(windows))]
-                deleting_destructor: DxcIncludeHandlerWrapper::dummy,
-                load_source: DxcIncludeHandlerWrapper::load_source,
-            };
-
-            Some(Box::new(DxcIncludeHandlerWrapper {
-                vtable: Box::new(vtable),
+            let mut wrapper = DxcIncludeHandlerWrapper {
                 handler: include_handler,
                 library,
                 pinned: vec![],
-            }))
+                includes: IncludeTracker::default(),
+                panic: None,
+            };
+            wrapper.includes.track(source_name, &blob.to_string_lossy());
+
+            Some(ComObjectRef::new(wrapper))
         } else {
             None
         }
     }
 
+    /// The panic caught in the include handler during the last call into the compiler,
+    /// as [`HassleError::IncludeHandlerPanic`].
+    fn take_include_handler_panic(
+        handler_wrapper: Option<ComObjectRef<DxcIncludeHandlerWrapper>>,
+    ) -> Option<HassleError> {
+        let panic = handler_wrapper?
+            .with_inner(|wrapper| wrapper.panic.take())
+            .flatten()?;
+        Some(HassleError::IncludeHandlerPanic(panic_message(&*panic)))
+    }
+
     pub fn compile(
         &self,
         blob: &DxcBlobEncoding,
         source_name: &str,
         entry_point: &str,
-        target_profile: &str,
+        target_profile: impl Into<String>,
         args: &[&str],
-        include_handler: Option<&mut dyn DxcIncludeHandler>,
+        include_handler: Option<&mut dyn DxcIncludeHandler2>,
         defines: &[(&str, Option<&str>)],
-    ) -> Result<DxcOperationResult, (DxcOperationResult, HRESULT)> {
+    ) -> Result<DxcOperationResult, (DxcOperationResult, HassleError)> {
         let mut wide_args = vec![];
         let mut dxc_args = vec![];
         Self::prep_args(args, &mut wide_args, &mut dxc_args);
@@ -268,7 +725,8 @@ impl DxcCompiler {
         let mut dxc_defines = vec![];
         Self::prep_defines(defines, &mut wide_defines, &mut dxc_defines);
 
-        let handler_wrapper = Self::prep_include_handler(&self.library, include_handler);
+        let handler_wrapper =
+            Self::prep_include_handler(&self.library, include_handler, source_name, blob);
 
         let mut result: ComPtr<IDxcOperationResult> = ComPtr::new();
         let result_hr = unsafe {
@@ -276,25 +734,29 @@ impl DxcCompiler {
                 blob.inner.as_ptr(),
                 to_wide(source_name).as_ptr(),
                 to_wide(entry_point).as_ptr(),
-                to_wide(target_profile).as_ptr(),
+                to_wide(&target_profile.into()).as_ptr(),
                 dxc_args.as_ptr(),
                 dxc_args.len() as u32,
                 dxc_defines.as_ptr(),
                 dxc_defines.len() as u32,
                 handler_wrapper
                     .as_ref()
-                    .map_or(std::ptr::null(), |v| &**v as *const _ as *const _),
+                    .map_or(std::ptr::null(), ComObjectRef::as_ptr),
                 result.as_mut_ptr(),
             )
         };
+        let panic = DxcCompiler::take_include_handler_panic(handler_wrapper);
 
         let mut compile_error = 0u32;
         let status_hr = unsafe { result.get_status(&mut compile_error) };
 
-        if !result_hr.is_err() && !status_hr.is_err() && compile_error == 0 {
-            Ok(DxcOperationResult::new(result))
+        if panic.is_none() && !result_hr.is_err() && !status_hr.is_err() && compile_error == 0 {
+            Ok(DxcOperationResult::new(result, self.lib.clone()).produced_by(&self.identity))
         } else {
-            Err((DxcOperationResult::new(result), result_hr))
+            Err((
+                DxcOperationResult::new(result, self.lib.clone()).produced_by(&self.identity),
+                panic.unwrap_or(HassleError::Win32Error(result_hr)),
+            ))
         }
     }
 
@@ -303,11 +765,11 @@ impl DxcCompiler {
         blob: &DxcBlobEncoding,
         source_name: &str,
         entry_point: &str,
-        target_profile: &str,
+        target_profile: impl Into<String>,
         args: &[&str],
-        include_handler: Option<&mut dyn DxcIncludeHandler>,
+        include_handler: Option<&mut dyn DxcIncludeHandler2>,
         defines: &[(&str, Option<&str>)],
-    ) -> Result<(DxcOperationResult, String, DxcBlob), (DxcOperationResult, HRESULT)> {
+    ) -> Result<(DxcOperationResult, String, DxcBlob), (DxcOperationResult, HassleError)> {
         let mut wide_args = vec![];
         let mut dxc_args = vec![];
         Self::prep_args(args, &mut wide_args, &mut dxc_args);
@@ -316,7 +778,8 @@ impl DxcCompiler {
         let mut dxc_defines = vec![];
         Self::prep_defines(defines, &mut wide_defines, &mut dxc_defines);
 
-        let handler_wrapper = Self::prep_include_handler(&self.library, include_handler);
+        let handler_wrapper =
+            Self::prep_include_handler(&self.library, include_handler, source_name, blob);
 
         let mut result: ComPtr<IDxcOperationResult> = ComPtr::new();
         let mut debug_blob: ComPtr<IDxcBlob> = ComPtr::new();
@@ -327,31 +790,35 @@ impl DxcCompiler {
                 blob.inner.as_ptr(),
                 to_wide(source_name).as_ptr(),
                 to_wide(entry_point).as_ptr(),
-                to_wide(target_profile).as_ptr(),
+                to_wide(&target_profile.into()).as_ptr(),
                 dxc_args.as_ptr(),
                 dxc_args.len() as u32,
                 dxc_defines.as_ptr(),
                 dxc_defines.len() as u32,
                 handler_wrapper
                     .as_ref()
-                    .map_or(std::ptr::null(), |v| &**v as *const _ as *const _),
+                    .map_or(std::ptr::null(), ComObjectRef::as_ptr),
                 result.as_mut_ptr(),
                 &mut debug_filename,
                 debug_blob.as_mut_ptr(),
             )
         };
+        let panic = DxcCompiler::take_include_handler_panic(handler_wrapper);
 
         let mut compile_error = 0u32;
         let status_hr = unsafe { result.get_status(&mut compile_error) };
 
-        if !result_hr.is_err() && !status_hr.is_err() && compile_error == 0 {
+        if panic.is_none() && !result_hr.is_err() && !status_hr.is_err() && compile_error == 0 {
             Ok((
-                DxcOperationResult::new(result),
+                DxcOperationResult::new(result, self.lib.clone()).produced_by(&self.identity),
                 from_wide(debug_filename),
-                DxcBlob::new(debug_blob),
+                DxcBlob::new(debug_blob, self.lib.clone()),
             ))
         } else {
-            Err((DxcOperationResult::new(result), result_hr))
+            Err((
+                DxcOperationResult::new(result, self.lib.clone()).produced_by(&self.identity),
+                panic.unwrap_or(HassleError::Win32Error(result_hr)),
+            ))
         }
     }
 
@@ -360,9 +827,9 @@ impl DxcCompiler {
         blob: &DxcBlobEncoding,
         source_name: &str,
         args: &[&str],
-        include_handler: Option<&mut dyn DxcIncludeHandler>,
+        include_handler: Option<&mut dyn DxcIncludeHandler2>,
         defines: &[(&str, Option<&str>)],
-    ) -> Result<DxcOperationResult, (DxcOperationResult, HRESULT)> {
+    ) -> Result<DxcOperationResult, (DxcOperationResult, HassleError)> {
         let mut wide_args = vec![];
         let mut dxc_args = vec![];
         Self::prep_args(args, &mut wide_args, &mut dxc_args);
@@ -371,7 +838,8 @@ impl DxcCompiler {
         let mut dxc_defines = vec![];
         Self::prep_defines(defines, &mut wide_defines, &mut dxc_defines);
 
-        let handler_wrapper = Self::prep_include_handler(&self.library, include_handler);
+        let handler_wrapper =
+            Self::prep_include_handler(&self.library, include_handler, source_name, blob);
 
         let mut result: ComPtr<IDxcOperationResult> = ComPtr::new();
         let result_hr = unsafe {
@@ -384,18 +852,22 @@ impl DxcCompiler {
                 dxc_defines.len() as u32,
                 handler_wrapper
                     .as_ref()
-                    .map_or(std::ptr::null(), |v| &**v as *const _ as *const _),
+                    .map_or(std::ptr::null(), ComObjectRef::as_ptr),
                 result.as_mut_ptr(),
             )
         };
+        let panic = DxcCompiler::take_include_handler_panic(handler_wrapper);
 
         let mut compile_error = 0u32;
         let status_hr = unsafe { result.get_status(&mut compile_error) };
 
-        if !result_hr.is_err() && !status_hr.is_err() && compile_error == 0 {
-            Ok(DxcOperationResult::new(result))
+        if panic.is_none() && !result_hr.is_err() && !status_hr.is_err() && compile_error == 0 {
+            Ok(DxcOperationResult::new(result, self.lib.clone()).produced_by(&self.identity))
         } else {
-            Err((DxcOperationResult::new(result), result_hr))
+            Err((
+                DxcOperationResult::new(result, self.lib.clone()).produced_by(&self.identity),
+                panic.unwrap_or(HassleError::Win32Error(result_hr)),
+            ))
         }
     }
 
@@ -406,18 +878,471 @@ impl DxcCompiler {
                 .disassemble(blob.inner.as_ptr(), result_blob.as_mut_ptr())
         }
         .result()?;
-        Ok(DxcBlobEncoding::new(result_blob))
+        Ok(DxcBlobEncoding::new(result_blob, self.lib.clone()))
+    }
+}
+
+#[derive(Debug)]
+pub struct DxcCompiler3 {
+    inner: ComPtr<IDxcCompiler3>,
+    library: DxcLibrary,
+    identity: DxcIdentity,
+    lib: Arc<Library>,
+}
+
+impl DxcCompiler3 {
+    fn new(
+        inner: ComPtr<IDxcCompiler3>,
+        library: DxcLibrary,
+        identity: DxcIdentity,
+        lib: Arc<Library>,
+    ) -> Self {
+        Self {
+            inner,
+            library,
+            identity,
+            lib,
+        }
+    }
+
+    pub fn identity(&self) -> &DxcIdentity {
+        &self.identity
+    }
+
+    fn prep_buffer(blob: &DxcBlobEncoding) -> DxcBuffer {
+        let mut known = 0u32;
+        let mut code_page = 0u32;
+        let encoding_hr = unsafe { blob.inner.get_encoding(&mut known, &mut code_page) };
+
+        DxcBuffer {
+            ptr: unsafe { blob.inner.get_buffer_pointer() },
+            size: unsafe { blob.inner.get_buffer_size() },
+            // Unknown encodings are detected by the compiler
+            encoding: if !encoding_hr.is_err() && known != 0 {
+                code_page
+            } else {
+                0
+            },
+        }
+    }
+
+    /// `IDxcCompiler3` takes everything through its argument list: the source name,
+    /// entry point, target profile and defines are passed the same way `dxc` receives them.
+    fn prep_compile_args(
+        source_name: &str,
+        entry_point: &str,
+        target_profile: &str,
+        args: &[&str],
+        defines: &[(&str, Option<&str>)],
+    ) -> Vec<String> {
+        let mut compile_args = vec![
+            source_name.to_owned(),
+            "-E".to_owned(),
+            entry_point.to_owned(),
+            "-T".to_owned(),
+            target_profile.to_owned(),
+        ];
+
+        compile_args.extend(args.iter().map(|a| a.to_string()));
+
+        for (name, value) in defines {
+            compile_args.push(format!("-D{}={}", name, value.unwrap_or("1")));
+        }
+
+        compile_args
+    }
+
+    /// Compiles `blob` and returns all outputs produced by the compiler in a single [`DxcResult`].
+    pub fn compile(
+        &self,
+        blob: &DxcBlobEncoding,
+        source_name: &str,
+        entry_point: &str,
+        target_profile: impl Into<String>,
+        args: &[&str],
+        include_handler: Option<&mut dyn DxcIncludeHandler2>,
+        defines: &[(&str, Option<&str>)],
+    ) -> Result<DxcResult, (DxcResult, HassleError)> {
+        let compile_args = Self::prep_compile_args(
+            source_name,
+            entry_point,
+            &target_profile.into(),
+            args,
+            defines,
+        );
+        let compile_args = compile_args.iter().map(String::as_str).collect::<Vec<_>>();
+
+        let mut wide_args = vec![];
+        let mut dxc_args = vec![];
+        DxcCompiler::prep_args(&compile_args, &mut wide_args, &mut dxc_args);
+
+        let handler_wrapper =
+            DxcCompiler::prep_include_handler(&self.library, include_handler, source_name, blob);
+
+        let buffer = Self::prep_buffer(blob);
+
+        let mut result: ComPtr<IDxcResult> = ComPtr::new();
+        let result_hr = unsafe {
+            self.inner.compile(
+                &buffer,
+                dxc_args.as_ptr(),
+                dxc_args.len() as u32,
+                handler_wrapper
+                    .as_ref()
+                    .map_or(std::ptr::null(), ComObjectRef::as_ptr),
+                &IID_IDxcResult,
+                result.as_mut_ptr::<std::ffi::c_void>(),
+            )
+        };
+        let panic = DxcCompiler::take_include_handler_panic(handler_wrapper);
+
+        let mut compile_error = 0u32;
+        let status_hr = unsafe { result.get_status(&mut compile_error) };
+
+        if panic.is_none() && !result_hr.is_err() && !status_hr.is_err() && compile_error == 0 {
+            Ok(DxcResult::new(result, self.lib.clone()).produced_by(&self.identity))
+        } else {
+            Err((
+                DxcResult::new(result, self.lib.clone()).produced_by(&self.identity),
+                panic.unwrap_or(HassleError::Win32Error(result_hr)),
+            ))
+        }
+    }
+
+    pub fn disassemble(&self, blob: &DxcBlob) -> Result<DxcResult> {
+        let buffer = DxcBuffer {
+            ptr: unsafe { blob.inner.get_buffer_pointer() },
+            size: unsafe { blob.inner.get_buffer_size() },
+            encoding: 0,
+        };
+
+        let mut result: ComPtr<IDxcResult> = ComPtr::new();
+        unsafe {
+            self.inner.disassemble(
+                &buffer,
+                &IID_IDxcResult,
+                result.as_mut_ptr::<std::ffi::c_void>(),
+            )
+        }
+        .result()?;
+        Ok(DxcResult::new(result, self.lib.clone()).produced_by(&self.identity))
+    }
+}
+
+#[derive(Debug)]
+pub struct DxcLinker {
+    inner: ComPtr<IDxcLinker>,
+    identity: DxcIdentity,
+    lib: Arc<Library>,
+}
+
+impl DxcLinker {
+    fn new(inner: ComPtr<IDxcLinker>, identity: DxcIdentity, lib: Arc<Library>) -> Self {
+        Self {
+            inner,
+            identity,
+            lib,
+        }
+    }
+
+    pub fn identity(&self) -> &DxcIdentity {
+        &self.identity
+    }
+
+    /// Registers a library compiled with a `lib_6_x` target profile under `lib_name`,
+    /// so that it can be referenced in [`DxcLinker::link()`].
+    pub fn register_library(&self, lib_name: &str, lib: &DxcBlob) -> Result<()> {
+        unsafe {
+            self.inner
+                .register_library(to_wide(lib_name).as_ptr(), lib.inner.as_ptr())
+        }
+        .result()
+    }
+
+    /// Links `entry_name` from the registered libraries in `lib_names` into a shader
+    /// for `target_profile`.
+    pub fn link(
+        &self,
+        entry_name: &str,
+        target_profile: impl Into<String>,
+        lib_names: &[&str],
+        args: &[&str],
+    ) -> Result<DxcOperationResult, (DxcOperationResult, HassleError)> {
+        let mut wide_lib_names = vec![];
+        let mut dxc_lib_names = vec![];
+        DxcCompiler::prep_args(lib_names, &mut wide_lib_names, &mut dxc_lib_names);
+
+        let mut wide_args = vec![];
+        let mut dxc_args = vec![];
+        DxcCompiler::prep_args(args, &mut wide_args, &mut dxc_args);
+
+        let mut result: ComPtr<IDxcOperationResult> = ComPtr::new();
+        let result_hr = unsafe {
+            self.inner.link(
+                to_wide(entry_name).as_ptr(),
+                to_wide(&target_profile.into()).as_ptr(),
+                dxc_lib_names.as_ptr(),
+                dxc_lib_names.len() as u32,
+                dxc_args.as_ptr(),
+                dxc_args.len() as u32,
+                result.as_mut_ptr(),
+            )
+        };
+
+        let mut link_error = 0u32;
+        let status_hr = unsafe { result.get_status(&mut link_error) };
+
+        if !result_hr.is_err() && !status_hr.is_err() && link_error == 0 {
+            Ok(DxcOperationResult::new(result, self.lib.clone()).produced_by(&self.identity))
+        } else {
+            Err((
+                DxcOperationResult::new(result, self.lib.clone()).produced_by(&self.identity),
+                HassleError::Win32Error(result_hr),
+            ))
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct DxcAssembler {
+    inner: ComPtr<IDxcAssembler>,
+    library: DxcLibrary,
+    lib: Arc<Library>,
+}
+
+impl DxcAssembler {
+    fn new(inner: ComPtr<IDxcAssembler>, library: DxcLibrary, lib: Arc<Library>) -> Self {
+        Self {
+            inner,
+            library,
+            lib,
+        }
+    }
+
+    /// Assembles DXIL in LLVM IR text or bitcode form into a DXIL container.
+    pub fn assemble_to_container(&self, shader: &DxcBlob) -> Result<DxcBlob> {
+        let mut result: ComPtr<IDxcOperationResult> = ComPtr::new();
+        let result_hr = unsafe {
+            self.inner
+                .assemble_to_container(shader.inner.as_ptr(), result.as_mut_ptr())
+        };
+
+        if result.is_null() {
+            return Err(HassleError::Win32Error(result_hr));
+        }
+
+        let result = DxcOperationResult::new(result, self.lib.clone());
+        let status = result.get_status()?;
+
+        if !result_hr.is_err() && status == 0 {
+            result.get_result()
+        } else {
+            let errors = result.get_error_buffer()?.to_string_lossy();
+            if errors.is_empty() {
+                Err(HassleError::Win32Error(result_hr))
+            } else {
+                Err(HassleError::AssembleError(errors))
+            }
+        }
+    }
+
+    /// Assembles DXIL in LLVM IR text form, as produced by [`DxcCompiler::disassemble()`],
+    /// into a DXIL container.
+    pub fn assemble(&self, ir: &str) -> Result<DxcBlob> {
+        let blob = self.library.create_blob_with_encoding_from_str(ir)?;
+        self.assemble_to_container(&blob.into())
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct DxcOptimizerPassArg {
+    pub name: String,
+    pub description: String,
+}
+
+/// Description of a pass that can be passed to [`DxcOptimizer::run_optimizer()`]
+/// as `-<option_name>`.
+#[derive(Clone, Debug)]
+pub struct DxcOptimizerPass {
+    pub option_name: String,
+    pub description: String,
+    pub args: Vec<DxcOptimizerPassArg>,
+}
+
+impl DxcOptimizerPass {
+    fn new(pass: ComPtr<IDxcOptimizerPass>) -> Result<Self> {
+        let mut option_name: LPWSTR = std::ptr::null_mut();
+        unsafe { pass.get_option_name(&mut option_name) }.result()?;
+        let option_name = from_co_task_wide(option_name);
+
+        let mut description: LPWSTR = std::ptr::null_mut();
+        unsafe { pass.get_description(&mut description) }.result()?;
+        let description = from_co_task_wide(description);
+
+        let mut arg_count = 0u32;
+        unsafe { pass.get_option_arg_count(&mut arg_count) }.result()?;
+
+        let args = (0..arg_count)
+            .map(|arg_idx| {
+                let mut name: LPWSTR = std::ptr::null_mut();
+                unsafe { pass.get_option_arg_name(arg_idx, &mut name) }.result()?;
+                let name = from_co_task_wide(name);
+
+                let mut description: LPWSTR = std::ptr::null_mut();
+                unsafe { pass.get_option_arg_description(arg_idx, &mut description) }.result()?;
+                let description = from_co_task_wide(description);
+
+                Ok(DxcOptimizerPassArg { name, description })
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(Self {
+            option_name,
+            description,
+            args,
+        })
+    }
+}
+
+#[derive(Debug)]
+pub struct DxcOptimizer {
+    inner: ComPtr<IDxcOptimizer>,
+    lib: Arc<Library>,
+}
+
+impl DxcOptimizer {
+    fn new(inner: ComPtr<IDxcOptimizer>, lib: Arc<Library>) -> Self {
+        Self { inner, lib }
+    }
+
+    pub fn get_available_passes(&self) -> Result<Vec<DxcOptimizerPass>> {
+        let mut pass_count = 0u32;
+        unsafe { self.inner.get_available_pass_count(&mut pass_count) }.result()?;
+
+        (0..pass_count)
+            .map(|index| {
+                let mut pass: ComPtr<IDxcOptimizerPass> = ComPtr::new();
+                unsafe { self.inner.get_available_pass(index, pass.as_mut_ptr()) }.result()?;
+                DxcOptimizerPass::new(pass)
+            })
+            .collect()
+    }
+
+    /// Runs the pass pipeline described by `options`, such as `["-mem2reg", "-dce"]`,
+    /// on a DXIL module.
+    ///
+    /// When `output_text` is set the text printed by the passes, for example through
+    /// `-print-module`, is returned alongside the optimized module.
+    pub fn run_optimizer(
+        &self,
+        blob: &DxcBlob,
+        options: &[&str],
+        output_text: bool,
+    ) -> Result<(DxcBlob, Option<String>)> {
+        let mut wide_options = vec![];
+        let mut dxc_options = vec![];
+        DxcCompiler::prep_args(options, &mut wide_options, &mut dxc_options);
+
+        let mut output_module: ComPtr<IDxcBlob> = ComPtr::new();
+        let mut text: ComPtr<IDxcBlobEncoding> = ComPtr::new();
+
+        unsafe {
+            self.inner.run_optimizer(
+                blob.inner.as_ptr(),
+                dxc_options.as_ptr(),
+                dxc_options.len() as u32,
+                output_module.as_mut_ptr(),
+                if output_text {
+                    text.as_mut_ptr()
+                } else {
+                    std::ptr::null_mut()
+                },
+            )
+        }
+        .result()?;
+
+        let text = if output_text {
+            Some(DxcBlobEncoding::new(text, self.lib.clone()).to_string_lossy())
+        } else {
+            None
+        };
+
+        Ok((DxcBlob::new(output_module, self.lib.clone()), text))
+    }
+}
+
+/// Edits the parts of a DXIL container, identified by their `DFCC_*` four character code.
+///
+/// The serialized container is not signed, use [`crate::fake_sign_dxil_in_place()`] or
+/// [`crate::validate_dxil()`] on the result to sign it again.
+#[derive(Debug)]
+pub struct DxcContainerBuilder {
+    inner: ComPtr<IDxcContainerBuilder>,
+    lib: Arc<Library>,
+}
+
+impl DxcContainerBuilder {
+    fn new(inner: ComPtr<IDxcContainerBuilder>, lib: Arc<Library>) -> Self {
+        Self { inner, lib }
+    }
+
+    /// Loads the parts of an existing container.
+    pub fn load(&self, container: &DxcBlob) -> Result<()> {
+        unsafe { self.inner.load(container.inner.as_ptr()) }.result()
+    }
+
+    /// Adds a part, such as a root signature with [`DFCC_ROOT_SIGNATURE`].
+    pub fn add_part(&self, four_cc: u32, part: &DxcBlob) -> Result<()> {
+        unsafe { self.inner.add_part(four_cc, part.inner.as_ptr()) }.result()
+    }
+
+    /// Removes a part, such as debug info with [`DFCC_SHADER_DEBUG_INFO_DXIL`] or
+    /// reflection with [`DFCC_SHADER_STATISTICS`].
+    pub fn remove_part(&self, four_cc: u32) -> Result<()> {
+        unsafe { self.inner.remove_part(four_cc) }.result()
+    }
+
+    pub fn serialize_container(&self) -> Result<DxcBlob> {
+        let mut result: ComPtr<IDxcOperationResult> = ComPtr::new();
+        unsafe { self.inner.seralize_container(result.as_mut_ptr()) }.result()?;
+
+        let result = DxcOperationResult::new(result, self.lib.clone());
+        HRESULT(result.get_status()? as i32).result()?;
+        result.get_result()
     }
 }
 
 #[derive(Debug)]
 pub struct DxcLibrary {
     inner: ComPtr<IDxcLibrary>,
+    lib: Arc<Library>,
 }
 
 impl DxcLibrary {
-    fn new(inner: ComPtr<IDxcLibrary>) -> Self {
-        Self { inner }
+    fn new(inner: ComPtr<IDxcLibrary>, lib: Arc<Library>) -> Self {
+        Self { inner, lib }
+    }
+
+    /// Loads the contents of the file at `path`, decoded with `code_page` or with the
+    /// encoding detected from its byte order mark when `None`.
+    pub fn create_blob_from_file(
+        &self,
+        path: impl AsRef<Path>,
+        code_page: Option<u32>,
+    ) -> Result<DxcBlobEncoding> {
+        let mut blob: ComPtr<IDxcBlobEncoding> = ComPtr::new();
+
+        unsafe {
+            self.inner.create_blob_from_file(
+                to_wide(&path.as_ref().to_string_lossy()).as_ptr(),
+                code_page
+                    .as_ref()
+                    .map_or(std::ptr::null(), |code_page| code_page as *const u32),
+                blob.as_mut_ptr(),
+            )
+        }
+        .result()?;
+        Ok(DxcBlobEncoding::new(blob, self.lib.clone()))
     }
 
     pub fn create_blob_with_encoding(&self, data: &[u8]) -> Result<DxcBlobEncoding> {
@@ -432,7 +1357,27 @@ impl DxcLibrary {
             )
         }
         .result()?;
-        Ok(DxcBlobEncoding::new(blob))
+        Ok(DxcBlobEncoding::new(blob, self.lib.clone()))
+    }
+
+    /// Creates a blob that references `data` without copying it, tagged with `code_page`.
+    pub(crate) fn create_blob_with_code_page(
+        &self,
+        data: &[u8],
+        code_page: u32,
+    ) -> Result<DxcBlobEncoding> {
+        let mut blob: ComPtr<IDxcBlobEncoding> = ComPtr::new();
+
+        unsafe {
+            self.inner.create_blob_with_encoding_from_pinned(
+                data.as_ptr().cast(),
+                data.len() as u32,
+                code_page,
+                blob.as_mut_ptr(),
+            )
+        }
+        .result()?;
+        Ok(DxcBlobEncoding::new(blob, self.lib.clone()))
     }
 
     pub fn create_blob_with_encoding_from_str(&self, text: &str) -> Result<DxcBlobEncoding> {
@@ -448,7 +1393,7 @@ impl DxcLibrary {
             )
         }
         .result()?;
-        Ok(DxcBlobEncoding::new(blob))
+        Ok(DxcBlobEncoding::new(blob, self.lib.clone()))
     }
 
     pub fn get_blob_as_string(&self, blob: &DxcBlob) -> Result<String> {
@@ -460,56 +1405,94 @@ impl DxcLibrary {
         }
         .result()?;
 
-        Ok(String::from_utf8(DxcBlob::new((&blob_utf8).into()).to_vec()).unwrap())
+        Ok(
+            String::from_utf8(DxcBlob::new((&blob_utf8).into(), self.lib.clone()).to_vec())
+                .unwrap(),
+        )
     }
 }
 
-#[derive(Debug)]
-pub struct Dxc {
-    dxc_lib: Library,
-}
-
-#[cfg(target_os = "windows")]
-fn dxcompiler_lib_name() -> &'static Path {
-    Path::new("dxcompiler.dll")
+/// Identifies a loaded `dxcompiler` library, to tell apart multiple libraries loaded
+/// side by side and the results they produced.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct DxcIdentity {
+    /// Path the library was loaded from.
+    pub path: PathBuf,
+    /// `None` for libraries that do not implement `IDxcVersionInfo`.
+    pub version: Option<DxcVersion>,
 }
 
-#[cfg(any(target_os = "linux", target_os = "android"))]
-fn dxcompiler_lib_name() -> &'static Path {
-    Path::new("./libdxcompiler.so")
+impl std::fmt::Display for DxcIdentity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.version {
+            Some(version) => write!(f, "{} ({})", self.path.display(), version),
+            None => write!(f, "{} (unknown version)", self.path.display()),
+        }
+    }
 }
 
-#[cfg(target_os = "macos")]
-fn dxcompiler_lib_name() -> &'static Path {
-    Path::new("./libdxcompiler.dynlib")
+/// A loaded `dxcompiler` library.
+///
+/// `Dxc` is `Send` and `Sync`, so a single instance can be shared between threads to
+/// create a compiler for each of them, see [`crate::CompilerPool`].
+///
+/// Libraries at different paths can be loaded side by side, every instance keeps its
+/// own [`DxcIdentity`], see also [`crate::DxcRegistry`].
+///
+/// Objects created from a `Dxc` keep the library loaded, so they remain valid after the
+/// `Dxc` itself is dropped. Clones share the loaded library.
+#[derive(Clone, Debug)]
+pub struct Dxc {
+    dxc_lib: Arc<Library>,
+    identity: DxcIdentity,
 }
 
 impl Dxc {
-    /// `dxc_path` can point to a library directly or the directory containing the library,
-    /// in which case the appended filename depends on the platform.
+    /// Loads the `dxcompiler` library.
+    ///
+    /// `lib_path` can point to a library directly or the directory containing the library,
+    /// in which case the appended filename depends on the platform. When `None`, the
+    /// library is searched for in:
+    ///
+    /// 1. the path in the `DXC_PATH` environment variable, a file or directory;
+    /// 2. the directory of the current executable;
+    /// 3. the current working directory;
+    /// 4. the search paths of the system loader.
+    ///
+    /// The first library that exports `DxcCreateInstance` is used, otherwise the returned
+    /// [`HassleError::LoadLibraryError`] lists every path that was tried.
     pub fn new(lib_path: Option<PathBuf>) -> Result<Self> {
-        let lib_path = if let Some(lib_path) = lib_path {
-            if lib_path.is_file() {
-                lib_path
-            } else {
-                lib_path.join(&dxcompiler_lib_name())
-            }
-        } else {
-            dxcompiler_lib_name().to_owned()
+        let (dxc_lib, path) = load_library(
+            lib_path.as_deref(),
+            Some(DXC_PATH_ENV),
+            DXCOMPILER_LIB_NAMES,
+        )?;
+
+        let mut dxc = Self {
+            dxc_lib: Arc::new(dxc_lib),
+            identity: DxcIdentity {
+                path,
+                version: None,
+            },
         };
-        let dxc_lib =
-            unsafe { Library::new(&lib_path) }.map_err(|e| HassleError::LoadLibraryError {
-                filename: lib_path,
-                inner: e,
-            })?;
+        dxc.identity.version = dxc.version().ok();
+
+        Ok(dxc)
+    }
 
-        Ok(Self { dxc_lib })
+    pub fn identity(&self) -> &DxcIdentity {
+        &self.identity
     }
 
     pub(crate) fn get_dxc_create_instance(&self) -> Result<Symbol<DxcCreateInstanceProc>> {
         Ok(unsafe { self.dxc_lib.get(b"DxcCreateInstance\0")? })
     }
 
+    /// Shared handle to the loaded library, which every object created from it holds on to.
+    pub(crate) fn library_handle(&self) -> Arc<Library> {
+        self.dxc_lib.clone()
+    }
+
     pub fn create_compiler(&self) -> Result<DxcCompiler> {
         let mut compiler: ComPtr<IDxcCompiler2> = ComPtr::new();
 
@@ -519,7 +1502,98 @@ impl Dxc {
             compiler.as_mut_ptr(),
         )
         .result()?;
-        Ok(DxcCompiler::new(compiler, self.create_library()?))
+        Ok(DxcCompiler::new(
+            compiler,
+            self.create_library()?,
+            self.identity.clone(),
+            self.dxc_lib.clone(),
+        ))
+    }
+
+    /// Creates a compiler exposing the `IDxcCompiler3` interface, which returns every
+    /// output of a compilation (object, PDB, reflection, hash, ...) in one [`DxcResult`].
+    ///
+    /// Requires a `dxcompiler` library from `release-1.5` or newer.
+    pub fn create_compiler3(&self) -> Result<DxcCompiler3> {
+        let mut compiler: ComPtr<IDxcCompiler3> = ComPtr::new();
+
+        self.get_dxc_create_instance()?(
+            &CLSID_DxcCompiler,
+            &IID_IDxcCompiler3,
+            compiler.as_mut_ptr(),
+        )
+        .result()?;
+        Ok(DxcCompiler3::new(
+            compiler,
+            self.create_library()?,
+            self.identity.clone(),
+            self.dxc_lib.clone(),
+        ))
+    }
+
+    pub fn create_linker(&self) -> Result<DxcLinker> {
+        let mut linker: ComPtr<IDxcLinker> = ComPtr::new();
+
+        self.get_dxc_create_instance()?(&CLSID_DxcLinker, &IID_IDxcLinker, linker.as_mut_ptr())
+            .result()?;
+        Ok(DxcLinker::new(
+            linker,
+            self.identity.clone(),
+            self.dxc_lib.clone(),
+        ))
+    }
+
+    pub fn create_assembler(&self) -> Result<DxcAssembler> {
+        let mut assembler: ComPtr<IDxcAssembler> = ComPtr::new();
+
+        self.get_dxc_create_instance()?(
+            &CLSID_DxcAssembler,
+            &IID_IDxcAssembler,
+            assembler.as_mut_ptr(),
+        )
+        .result()?;
+        Ok(DxcAssembler::new(
+            assembler,
+            self.create_library()?,
+            self.dxc_lib.clone(),
+        ))
+    }
+
+    pub fn create_optimizer(&self) -> Result<DxcOptimizer> {
+        let mut optimizer: ComPtr<IDxcOptimizer> = ComPtr::new();
+
+        self.get_dxc_create_instance()?(
+            &CLSID_DxcOptimizer,
+            &IID_IDxcOptimizer,
+            optimizer.as_mut_ptr(),
+        )
+        .result()?;
+        Ok(DxcOptimizer::new(optimizer, self.dxc_lib.clone()))
+    }
+
+    pub fn create_container_builder(&self) -> Result<DxcContainerBuilder> {
+        let mut builder: ComPtr<IDxcContainerBuilder> = ComPtr::new();
+
+        self.get_dxc_create_instance()?(
+            &CLSID_DxcContainerBuilder,
+            &IID_IDxcContainerBuilder,
+            builder.as_mut_ptr(),
+        )
+        .result()?;
+        Ok(DxcContainerBuilder::new(builder, self.dxc_lib.clone()))
+    }
+
+    /// Version and commit information of the loaded `dxcompiler` library.
+    pub fn version(&self) -> Result<DxcVersion> {
+        let mut version: ComPtr<IDxcVersionInfo> = ComPtr::new();
+
+        self.get_dxc_create_instance()?(
+            &CLSID_DxcCompiler,
+            &IID_IDxcVersionInfo,
+            version.as_mut_ptr(),
+        )
+        .result()?;
+        DxcVersion::new(&version)
     }
 
     pub fn create_library(&self) -> Result<DxcLibrary> {
@@ -527,7 +1601,7 @@ impl Dxc {
 
         self.get_dxc_create_instance()?(&CLSID_DxcLibrary, &IID_IDxcLibrary, library.as_mut_ptr())
             .result()?;
-        Ok(DxcLibrary::new(library))
+        Ok(DxcLibrary::new(library, self.dxc_lib.clone()))
     }
 
     pub fn create_reflector(&self) -> Result<DxcReflector> {
@@ -539,20 +1613,118 @@ impl Dxc {
             reflector.as_mut_ptr(),
         )
         .result()?;
-        Ok(DxcReflector::new(reflector))
+        Ok(DxcReflector::new(reflector, self.dxc_lib.clone()))
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct DxcCommitInfo {
+    /// Number of commits in the history of the build.
+    pub count: u32,
+    pub hash: String,
+}
+
+/// Version of a loaded `dxcompiler` or `dxil` library.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct DxcVersion {
+    pub major: u32,
+    pub minor: u32,
+    /// `DXC_VERSION_INFO_FLAGS_*`
+    pub flags: u32,
+    /// Only available on libraries implementing `IDxcVersionInfo2`.
+    pub commit: Option<DxcCommitInfo>,
+}
+
+impl DxcVersion {
+    fn new(version: &ComPtr<IDxcVersionInfo>) -> Result<Self> {
+        let mut major = 0;
+        let mut minor = 0;
+        unsafe { version.get_version(&mut major, &mut minor) }.result()?;
+
+        let mut flags = 0;
+        unsafe { version.get_flags(&mut flags) }.result()?;
+
+        let version2: ComPtr<IDxcVersionInfo2> = version.into();
+        let commit = if version2.is_null() {
+            None
+        } else {
+            let mut count = 0;
+            let mut hash: *mut u8 = std::ptr::null_mut();
+            unsafe { version2.get_commit_info(&mut count, &mut hash) }.result()?;
+
+            let commit_hash = if hash.is_null() {
+                String::new()
+            } else {
+                let commit_hash = from_lpstr(hash.cast());
+                unsafe { CoTaskMemFree(hash.cast()) };
+                commit_hash
+            };
+
+            Some(DxcCommitInfo {
+                count,
+                hash: commit_hash,
+            })
+        };
+
+        Ok(Self {
+            major,
+            minor,
+            flags,
+            commit,
+        })
+    }
+
+    /// Whether this version is `major.minor` or newer.
+    pub fn is_at_least(&self, major: u32, minor: u32) -> bool {
+        (self.major, self.minor) >= (major, minor)
+    }
+
+    /// Orders versions by `major.minor` and then by commit count, where known.
+    pub(crate) fn ordering_key(&self) -> (u32, u32, u32) {
+        (
+            self.major,
+            self.minor,
+            self.commit.as_ref().map_or(0, |commit| commit.count),
+        )
+    }
+
+    pub fn is_debug(&self) -> bool {
+        self.flags & DXC_VERSION_INFO_FLAGS_DEBUG != 0
+    }
+
+    /// Internal validator that does not sign its output.
+    pub fn is_internal(&self) -> bool {
+        self.flags & DXC_VERSION_INFO_FLAGS_INTERNAL != 0
+    }
+}
+
+impl std::fmt::Display for DxcVersion {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}.{}", self.major, self.minor)?;
+        if let Some(commit) = &self.commit {
+            write!(f, ".{} ({})", commit.count, commit.hash)?;
+        }
+        if self.is_debug() {
+            f.write_str(" debug")?;
+        }
+        if self.is_internal() {
+            f.write_str(" internal")?;
+        }
+        Ok(())
     }
 }
 
 #[derive(Debug)]
 pub struct DxcValidator {
     inner: ComPtr<IDxcValidator>,
+    lib: Arc<Library>,
 }
 
 pub type DxcValidatorVersion = (u32, u32);
 
 impl DxcValidator {
-    fn new(inner: ComPtr<IDxcValidator>) -> Self {
-        Self { inner }
+    fn new(inner: ComPtr<IDxcValidator>, lib: Arc<Library>) -> Self {
+        Self { inner, lib }
     }
 
     pub fn version(&self) -> Result<DxcValidatorVersion> {
@@ -587,7 +1759,7 @@ impl DxcValidator {
             Ok(blob)
         } else {
             Err((
-                DxcOperationResult::new(result),
+                DxcOperationResult::new(result, self.lib.clone()),
                 HassleError::Win32Error(result_hr),
             ))
         }
@@ -596,10 +1768,11 @@ impl DxcValidator {
 
 pub struct Reflection {
     inner: ComPtr<ID3D12ShaderReflection>,
+    lib: Arc<Library>,
 }
 impl Reflection {
-    fn new(inner: ComPtr<ID3D12ShaderReflection>) -> Self {
-        Self { inner }
+    fn new(inner: ComPtr<ID3D12ShaderReflection>, lib: Arc<Library>) -> Self {
+        Self { inner, lib }
     }
 
     pub fn thread_group_size(&self) -> [u32; 3] {
@@ -614,10 +1787,11 @@ impl Reflection {
 
 pub struct DxcReflector {
     inner: ComPtr<IDxcContainerReflection>,
+    lib: Arc<Library>,
 }
 impl DxcReflector {
-    fn new(inner: ComPtr<IDxcContainerReflection>) -> Self {
-        Self { inner }
+    fn new(inner: ComPtr<IDxcContainerReflection>, lib: Arc<Library>) -> Self {
+        Self { inner, lib }
     }
 
     pub fn reflect(&self, blob: DxcBlob) -> Result<Reflection> {
@@ -644,13 +1818,13 @@ impl DxcReflector {
             return Err(HassleError::Win32Error(result_hr));
         }
 
-        Ok(Reflection::new(reflection))
+        Ok(Reflection::new(reflection, self.lib.clone()))
     }
 }
 
 #[derive(Debug)]
 pub struct Dxil {
-    dxil_lib: Library,
+    dxil_lib: Arc<Library>,
 }
 
 impl Dxil {
@@ -662,26 +1836,16 @@ impl Dxil {
     }
 
     /// `dxil_path` can point to a library directly or the directory containing the library,
-    /// in which case `dxil.dll` is appended.
+    /// in which case `dxil.dll` is appended. When `None`, the library is searched for
+    /// in the directory of the current executable, the current working directory and
+    /// the search paths of the system loader.
     #[cfg(windows)]
     pub fn new(lib_path: Option<PathBuf>) -> Result<Self> {
-        let lib_path = if let Some(lib_path) = lib_path {
-            if lib_path.is_file() {
-                lib_path
-            } else {
-                lib_path.join("dxil.dll")
-            }
-        } else {
-            PathBuf::from("dxil.dll")
-        };
-
-        let dxil_lib =
-            unsafe { Library::new(&lib_path) }.map_err(|e| HassleError::LoadLibraryError {
-                filename: lib_path.to_owned(),
-                inner: e,
-            })?;
+        let (dxil_lib, _) = load_library(lib_path.as_deref(), None, DXIL_LIB_NAMES)?;
 
-        Ok(Self { dxil_lib })
+        Ok(Self {
+            dxil_lib: Arc::new(dxil_lib),
+        })
     }
 
     fn get_dxc_create_instance(&self) -> Result<Symbol<DxcCreateInstanceProc>> {
@@ -697,6 +1861,83 @@ impl Dxil {
             validator.as_mut_ptr(),
         )
         .result()?;
-        Ok(DxcValidator::new(validator))
+        Ok(DxcValidator::new(validator, self.dxil_lib.clone()))
+    }
+
+    /// Version and commit information of the loaded `dxil` library.
+    pub fn version(&self) -> Result<DxcVersion> {
+        let mut version: ComPtr<IDxcVersionInfo> = ComPtr::new();
+
+        self.get_dxc_create_instance()?(
+            &CLSID_DxcValidator,
+            &IID_IDxcVersionInfo,
+            version.as_mut_ptr(),
+        )
+        .result()?;
+        DxcVersion::new(&version)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A library that is never called into, `DxcLibrary` is only used once an
+    /// include was loaded.
+    fn unloaded_library() -> DxcLibrary {
+        #[cfg(unix)]
+        let lib = libloading::os::unix::Library::this();
+        #[cfg(windows)]
+        let lib = libloading::os::windows::Library::this().unwrap();
+        DxcLibrary::new(ComPtr::new(), Arc::new(lib.into()))
+    }
+
+    struct PanickingIncludeHandler {
+        calls: usize,
+    }
+
+    impl DxcIncludeHandler2 for PanickingIncludeHandler {
+        fn load_include(
+            &mut self,
+            filename: &str,
+            _includer: Option<&str>,
+        ) -> Result<DxcIncludeSource, DxcIncludeError> {
+            self.calls += 1;
+            panic!("cannot load {}", filename)
+        }
+    }
+
+    #[test]
+    fn include_handler_panic() {
+        let library = unloaded_library();
+        let mut handler = PanickingIncludeHandler { calls: 0 };
+        let wrapper = ComObjectRef::new(DxcIncludeHandlerWrapper {
+            handler: &mut handler,
+            pinned: vec![],
+            includes: IncludeTracker::default(),
+            library: &library,
+            panic: None,
+        });
+
+        let load = |filename: LPCWSTR| {
+            let mut blob: *mut IDxcBlob = std::ptr::null_mut();
+            let hr = DxcIncludeHandlerWrapper::load_source(wrapper.as_ptr(), filename, &mut blob);
+            assert!(blob.is_null());
+            hr
+        };
+        let filename = to_wide("./common.hlsl");
+
+        assert_eq!(load(std::ptr::null()), E_POINTER);
+        assert_eq!(load(filename.as_ptr()), E_FAIL);
+        // The handler is not called again after it panicked
+        assert_eq!(load(filename.as_ptr()), E_FAIL);
+
+        match DxcCompiler::take_include_handler_panic(Some(wrapper)) {
+            Some(HassleError::IncludeHandlerPanic(message)) => {
+                assert_eq!(message, "cannot load ./common.hlsl")
+            }
+            other => panic!("expected a panic, got {:?}", other),
+        }
+        assert_eq!(handler.calls, 1);
     }
 }